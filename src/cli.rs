@@ -1,8 +1,10 @@
-use std::path::PathBuf;
+use std::collections::BTreeMap;
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
 
 use anyhow::{bail, Context, Result};
 
-use clap::{CommandFactory, Parser, ValueHint};
+use clap::{CommandFactory, Parser, Subcommand, ValueHint};
 use log::info;
 
 use crate::overlay;
@@ -16,15 +18,20 @@ use crate::profile;
 	version = clap::crate_version!(),
 	author = clap::crate_authors!(),
 	disable_help_subcommand(true),
-	subcommand_negates_reqs(true)
+	subcommand_negates_reqs(true),
+	args_conflicts_with_subcommands(true)
 )]
 pub struct Options {
+	#[clap(subcommand)]
+	pub command: Option<Command>,
+
 	#[clap(
 		help = "Destination directory",
 		value_name("dir"),
-		value_hint(ValueHint::DirPath)
+		value_hint(ValueHint::DirPath),
+		required = true
 	)]
-	pub dst: PathBuf,
+	pub dst: Option<PathBuf>,
 
 	#[clap(
 		help = "Overlay source(s)",
@@ -36,18 +43,38 @@ pub struct Options {
 	#[clap(
 		short = 'p',
 		long = "profile",
-		help = "Path to the file with the profile definition",
+		help = "Path to the file with the profile definition, later profiles override earlier ones",
 		default_value = ".turboinstall.json",
 		value_name("/path/to/profile"),
-		value_hint(ValueHint::FilePath)
+		value_hint(ValueHint::FilePath),
+		global = true
+	)]
+	pub profile_paths: Vec<PathBuf>,
+
+	#[clap(
+		short = 'D',
+		long = "define",
+		help = "Set a profile variable, overriding the profile",
+		value_name("KEY=VALUE"),
+		value_parser = profile::parse_define,
+		global = true
+	)]
+	pub defines: Vec<(String, String)>,
+
+	#[clap(
+		long = "env-prefix",
+		help = "Import environment variables starting with this prefix into the profile",
+		value_name("prefix"),
+		global = true
 	)]
-	pub profile_path: PathBuf,
+	pub env_prefix: Option<String>,
 
 	#[clap(
 		short = 'f',
 		long = "format",
 		help = "Specify which format the profile uses",
-		value_name("fmt")
+		value_name("fmt"),
+		global = true
 	)]
 	pub profile_format: Option<profile::Format>,
 
@@ -55,29 +82,98 @@ pub struct Options {
 		short = 'l',
 		long = "link",
 		help = "Hard link files instead of copying",
-		conflicts_with("update")
+		conflicts_with("update"),
+		global = true
 	)]
 	pub hard_link: bool,
 
+	#[clap(
+		short = 'L',
+		long = "dereference",
+		help = "Install the targets of symlinks instead of the links",
+		global = true
+	)]
+	pub dereference: bool,
+
+	#[clap(
+		long = "expand-link-targets",
+		help = "Expand profile variables in absolute symlink targets",
+		conflicts_with("dereference"),
+		global = true
+	)]
+	pub expand_link_targets: bool,
+
 	#[clap(
 		short = 'n',
 		long = "no-clobber",
 		help = "Do not overwrite existing files",
-		conflicts_with("update")
+		conflicts_with("update"),
+		global = true
 	)]
 	pub no_overwrite: bool,
 
 	#[clap(
 		short = 'u',
 		long = "update",
-		help = "Overwrite only when the source path is newer"
+		help = "Overwrite only when the source path is newer",
+		global = true
 	)]
 	pub update: bool,
 
+	#[clap(
+		short = 'b',
+		long = "backup",
+		help = "Make a backup of each overwritten file",
+		value_name("mode"),
+		num_args(0..=1),
+		require_equals(true),
+		default_missing_value("simple")
+	)]
+	pub backup: Option<overlay::backup::BackupMode>,
+
+	#[clap(
+		short = 'S',
+		long = "suffix",
+		help = "Backup suffix for simple backups (default: ~, implies --backup)",
+		value_name("suffix")
+	)]
+	pub backup_suffix: Option<String>,
+
+	#[clap(
+		long = "backup-dir",
+		help = "Keep backups in this directory instead of next to the originals",
+		value_name("dir"),
+		value_hint(ValueHint::DirPath)
+	)]
+	pub backup_dir: Option<PathBuf>,
+
+	#[clap(
+		short = 'c',
+		long = "checksum",
+		help = "Skip files whose contents are already identical"
+	)]
+	pub checksum: bool,
+
+	#[clap(
+		short = 'j',
+		long = "jobs",
+		help = "Number of files to install in parallel",
+		default_value = "1",
+		value_name("N")
+	)]
+	pub jobs: NonZeroUsize,
+
+	#[clap(
+		long = "fsync",
+		help = "Flush installed files and their directories to disk"
+	)]
+	pub fsync: bool,
+
 	#[clap(
 		short = 'q',
 		long = "quiet",
-		help = "Don't print anything to the console"
+		help = "Don't print anything to the console",
+		global = true
 	)]
 	pub quiet: bool,
 
@@ -85,25 +181,49 @@ pub struct Options {
 		long = "ignore",
 		help = "Regex path pattern to ignore",
 		value_name("pattern"),
-		value_hint(ValueHint::AnyPath)
+		value_hint(ValueHint::AnyPath),
+		global = true
 	)]
 	pub ignore_patterns: Vec<String>,
 
+	#[clap(
+		long = "protect",
+		help = "Regex path pattern to never delete with --delete",
+		value_name("pattern"),
+		value_hint(ValueHint::AnyPath)
+	)]
+	pub protect_patterns: Vec<String>,
+
+	#[clap(
+		long = "template",
+		help = "Glob path pattern of files to render as templates",
+		value_name("pattern"),
+		value_hint(ValueHint::AnyPath),
+		global = true
+	)]
+	pub template_patterns: Vec<String>,
+
 	#[clap(
 		long = "ignore-file",
 		help = "Paths to extra ignore files",
 		value_name("path,path,..."),
 		value_delimiter(','),
-		value_hint(ValueHint::FilePath)
+		value_hint(ValueHint::FilePath),
+		global = true
 	)]
 	pub ignore_paths: Vec<PathBuf>,
 
-	#[clap(long = "no-abort", help = "Don't exit on error")]
+	#[clap(
+		long = "no-abort",
+		help = "Don't exit on error",
+		global = true
+	)]
 	pub no_abort: bool,
 
 	#[clap(
 		long = "dry-run",
-		help = "Do not perform any filesystem operations (implies --no-hooks)"
+		help = "Do not perform any filesystem operations (implies --no-hooks)",
+		global = true
 	)]
 	pub dry_run: bool,
 
@@ -121,15 +241,110 @@ pub struct Options {
 	#[clap(
 		long = "porcelain",
 		help = "Use machine readable output",
-		conflicts_with("quiet")
+		conflicts_with("quiet"),
+		global = true
 	)]
 	pub machine_readable: bool,
 
+	#[clap(
+		long = "manifest",
+		help = "Record the installed paths to this file",
+		value_name("/path/to/manifest"),
+		value_hint(ValueHint::FilePath)
+	)]
+	pub manifest_path: Option<PathBuf>,
+
+	#[clap(
+		long = "delete",
+		help = "Delete destination paths not provided by any overlay"
+	)]
+	pub delete: bool,
+
+	#[clap(
+		long = "transaction",
+		help = "Undo all changes if the install fails"
+	)]
+	pub transaction: bool,
+
 	#[clap(flatten)]
 	pub platform_options: overlay::platform::PlatformOptions,
 }
 
-pub fn init() -> Result<()> {
+#[derive(Debug, Subcommand)]
+pub enum Command {
+	#[clap(about = "Remove the paths recorded in a manifest")]
+	Uninstall(UninstallOptions),
+
+	#[clap(about = "Show what installing the overlays would change")]
+	Diff(DiffOptions),
+
+	#[clap(about = "Check that a destination matches its overlays")]
+	Verify(VerifyOptions),
+}
+
+#[derive(Debug, clap::Args)]
+pub struct UninstallOptions {
+	#[clap(
+		help = "Manifest written by `--manifest`",
+		value_name("/path/to/manifest"),
+		value_hint(ValueHint::FilePath)
+	)]
+	pub manifest_path: PathBuf,
+
+	#[clap(
+		long = "force",
+		help = "Remove files even if they were modified after the install"
+	)]
+	pub force: bool,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct DiffOptions {
+	#[clap(
+		help = "Destination directory",
+		value_name("dir"),
+		value_hint(ValueHint::DirPath)
+	)]
+	pub dst: PathBuf,
+
+	#[clap(
+		help = "Overlay source(s)",
+		value_name("dir"),
+		value_hint(ValueHint::DirPath),
+		required = true
+	)]
+	pub src: Vec<PathBuf>,
+
+	#[clap(
+		long = "content",
+		help = "Show the differences in the contents of text files"
+	)]
+	pub show_contents: bool,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct VerifyOptions {
+	#[clap(
+		help = "Destination directory",
+		value_name("dir"),
+		value_hint(ValueHint::DirPath)
+	)]
+	pub dst: PathBuf,
+
+	#[clap(
+		help = "Overlay source(s)",
+		value_name("dir"),
+		value_hint(ValueHint::DirPath),
+		required = true
+	)]
+	pub src: Vec<PathBuf>,
+}
+
+/// Exit code of `diff` and `verify` when the destination
+/// does not match the overlays
+const EXIT_DIFFERENCES: i32 = 2;
+
+pub fn init() -> Result<i32> {
 	#[cfg(debug_assertions)]
 	Options::command().debug_assert();
 
@@ -143,6 +358,60 @@ pub fn init() -> Result<()> {
 
 	init_log(&options)?;
 
+	match &options.command {
+		Some(Command::Uninstall(uninstall_options)) => {
+			uninstall(&options, uninstall_options)?
+		},
+		Some(Command::Diff(diff_options)) => {
+			return diff(&options, diff_options)
+		},
+		Some(Command::Verify(verify_options)) => {
+			return verify(&options, verify_options)
+		},
+		None => install(options)?,
+	}
+
+	Ok(0)
+}
+
+fn load_profile(options: &Options) -> Result<profile::Resolved> {
+	let mut profile = profile::Resolved::default();
+
+	// profiles that do not exist are treated as empty
+	for path in options.profile_paths.iter().filter(|x| x.exists()) {
+		profile.extend(profile::load_layered(
+			path,
+			options.profile_format,
+		)?);
+	}
+
+	// the environment overrides the files and definitions
+	// from the command line override both, their values are
+	// used as they are
+	if let Some(prefix) = &options.env_prefix {
+		profile.extend_literal(profile::from_env(prefix));
+	}
+
+	profile.extend_literal(options.defines.iter().cloned());
+
+	Ok(profile)
+}
+
+fn init_overlays(
+	dst: &Path,
+	src: &[PathBuf],
+) -> Result<Vec<overlay::Overlay>> {
+	src.iter()
+		.map(|src| overlay::Overlay::new(src, dst))
+		.collect()
+}
+
+fn install(mut options: Options) -> Result<()> {
+	let dst = match &options.dst {
+		Some(v) => v,
+		None => bail!("You must specify a destination path."),
+	};
+
 	if options.src.is_empty() {
 		bail!("You must specify at least one source path.")
 	}
@@ -151,46 +420,195 @@ pub fn init() -> Result<()> {
 		options.no_hooks = true;
 	}
 
-	// if the file does not exist default to using an empty profile
-	let profile = if options.profile_path.exists() {
-		profile::load_file(
-			&options.profile_path,
-			options.profile_format,
-		)?
-	} else {
-		Box::new(profile::NoProfile::new())
-	};
+	let profile = load_profile(&options)?;
+	let mut overlays = init_overlays(dst, &options.src)?;
 
-	// initialize our overlays
-	let mut overlays = Vec::with_capacity(options.src.len());
-	options.src.iter().try_for_each(|src| -> Result<()> {
-		overlays.push(overlay::Overlay::new(src, &options.dst)?);
-		Ok(())
-	})?;
+	// the overlays install to the canonical destination, so
+	// everything else has to use it too
+	let dst = &dst.canonicalize()?;
+
+	let mut session = overlay::Session::default();
 
-	for overlay in &mut overlays {
+	if !options.dry_run {
+		if options.manifest_path.is_some() {
+			session.manifest =
+				Some(overlay::manifest::Manifest::new());
+		}
+
+		if options.transaction {
+			session.transaction =
+				Some(overlay::transaction::Transaction::begin(dst)?);
+		}
+	}
+
+	let profile_names = options
+		.profile_paths
+		.iter()
+		.map(|x| x.to_string_lossy())
+		.collect::<Vec<_>>()
+		.join(", ");
+
+	// the profiles and entries of the installed overlays, which
+	// are only needed to delete extraneous files
+	let mut installed = Vec::new();
+
+	let r = overlays.iter_mut().try_for_each(|overlay| -> Result<()> {
 		use overlay::HookType;
 
 		let start = std::time::Instant::now();
 
-		overlay.run_hooks(
-			HookType::PreInstall,
-			&options,
-			profile.as_ref(),
-		)?;
+		let profile = overlay.profile(&profile)?;
+
+		overlay.run_hooks(HookType::PreInstall, &options, profile.as_ref())?;
 
-		overlay.install(profile.as_ref(), &options)?;
+		let entries = overlay.install(&profile, &options, &mut session)?;
 
-		overlay.run_hooks(
-			HookType::PostInstall,
+		overlay.run_hooks(HookType::PostInstall, &options, profile.as_ref())?;
+
+		if options.delete {
+			installed.push((profile, entries));
+		}
+
+		info!(target: "no_fmt", "{:>12} {} overlay(s) in {:.3}s", "Finished".bold().bright_green(), profile_names.dimmed(), start.elapsed().as_secs_f64());
+
+		Ok(())
+	});
+
+	let r = r.and_then(|_| {
+		if !options.delete {
+			return Ok(());
+		}
+
+		// paths ignored by the overlays are kept
+		let mut ignored = Vec::new();
+		let mut overlay_entries = Vec::new();
+		for (overlay, (profile, entries)) in
+			overlays.iter().zip(installed)
+		{
+			ignored.push(overlay::mirror::Ignored::new(
+				overlay, profile, &entries, &options,
+			)?);
+			overlay_entries.push(entries);
+		}
+
+		overlay::mirror::delete_extraneous(
+			dst,
+			&merge_entries(overlay_entries),
+			&mut ignored,
 			&options,
-			profile.as_ref(),
-		)?;
+			&mut session,
+		)
+	});
+
+	if let Some(transaction) = session.transaction {
+		match r {
+			Ok(_) => transaction.commit()?,
+			// nothing is left installed, so there is nothing
+			// for the manifest to record
+			Err(e) => {
+				transaction.rollback()?;
+				return Err(e);
+			},
+		}
+	}
 
-		info!(target: "no_fmt", "{:>12} {} overlay(s) in {:.3}s", "Finished".bold().bright_green(), options.profile_path.to_string_lossy().dimmed(), start.elapsed().as_secs_f64());
+	// save the manifest even if the install failed, so
+	// whatever was installed can still be removed
+	if let (Some(manifest), Some(path)) =
+		(&session.manifest, &options.manifest_path)
+	{
+		manifest.save(path)?;
 	}
 
-	Ok(())
+	r
+}
+
+/// Get the entries of all overlays, as they would end up
+/// in the destination.
+fn final_entries(
+	options: &Options,
+	profile: &profile::Resolved,
+	overlays: &[overlay::Overlay],
+) -> Result<Vec<overlay::Entry>> {
+	let entries = overlays
+		.iter()
+		.map(|overlay| {
+			overlay.entries(&overlay.profile(profile)?, options)
+		})
+		.collect::<Result<Vec<_>>>()?;
+
+	Ok(merge_entries(entries))
+}
+
+/// Merge the entries of each overlay, as they would end up in
+/// the destination.
+fn merge_entries(
+	overlay_entries: Vec<Vec<overlay::Entry>>,
+) -> Vec<overlay::Entry> {
+	// later overlays take precedence over earlier ones
+	let mut entries = BTreeMap::new();
+	for entry in overlay_entries.into_iter().flatten() {
+		// a whiteout hides everything below it
+		if entry.whiteout {
+			entries
+				.retain(|x: &PathBuf, _| !x.starts_with(&entry.dst));
+		}
+
+		entries.insert(entry.dst.clone(), entry);
+	}
+
+	entries.into_values().collect()
+}
+
+fn diff(
+	options: &Options,
+	diff_options: &DiffOptions,
+) -> Result<i32> {
+	let profile = load_profile(options)?;
+	let overlays =
+		init_overlays(&diff_options.dst, &diff_options.src)?;
+	let entries = final_entries(options, &profile, &overlays)?;
+
+	let pending = overlay::diff::run(
+		&entries,
+		options,
+		diff_options.show_contents,
+	)?;
+
+	Ok(if pending {
+		EXIT_DIFFERENCES
+	} else {
+		0
+	})
+}
+
+fn verify(
+	options: &Options,
+	verify_options: &VerifyOptions,
+) -> Result<i32> {
+	let profile = load_profile(options)?;
+	let overlays =
+		init_overlays(&verify_options.dst, &verify_options.src)?;
+	let entries = final_entries(options, &profile, &overlays)?;
+
+	let drifted = overlay::verify::run(&entries, options)?;
+
+	Ok(if drifted {
+		EXIT_DIFFERENCES
+	} else {
+		0
+	})
+}
+
+fn uninstall(
+	options: &Options,
+	uninstall_options: &UninstallOptions,
+) -> Result<()> {
+	let manifest = overlay::manifest::Manifest::load(
+		&uninstall_options.manifest_path,
+	)?;
+
+	manifest.uninstall(options, uninstall_options.force)
 }
 
 use colored::Colorize;
@@ -19,9 +19,12 @@ use std::process::exit;
 use log::error;
 
 fn main() {
-	if let Err(e) = cli::init() {
-		error!("{:#}", e);
+	match cli::init() {
+		Ok(code) => exit(code),
+		Err(e) => {
+			error!("{:#}", e);
 
-		exit(1);
+			exit(1);
+		},
 	}
 }
@@ -0,0 +1,133 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+
+use super::platform;
+use crate::cli::Options;
+
+static DEFAULT_SUFFIX: &str = "~";
+
+/// How backups of overwritten files are named.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum BackupMode {
+	/// Append the backup suffix.
+	Simple,
+	/// Append `.~N~`, where `N` is one more than the highest
+	/// existing backup.
+	Numbered,
+}
+
+/// Get the path the backup of `dst` should be written to,
+/// or `None` if backups are disabled.
+pub fn backup_path(
+	dst_root: &Path,
+	dst: &Path,
+	options: &Options,
+) -> Result<Option<PathBuf>> {
+	let base = match &options.backup_dir {
+		Some(backup_dir) => {
+			backup_dir.join(dst.strip_prefix(dst_root)?)
+		},
+		None if options.backup.is_some()
+			|| options.backup_suffix.is_some() =>
+		{
+			dst.to_path_buf()
+		},
+		None => return Ok(None),
+	};
+
+	// a backup directory without --backup or --suffix simply
+	// mirrors the overwritten files
+	let mode = match (options.backup, &options.backup_suffix) {
+		(None, None) => return Ok(Some(base)),
+		(mode, _) => mode.unwrap_or(BackupMode::Simple),
+	};
+
+	let path = match mode {
+		BackupMode::Simple => {
+			let suffix = options
+				.backup_suffix
+				.as_deref()
+				.unwrap_or(DEFAULT_SUFFIX);
+
+			append(&base, suffix)
+		},
+		BackupMode::Numbered => {
+			let n = highest_backup_number(&base)?.saturating_add(1);
+			append(&base, &format!(".~{}~", n))
+		},
+	};
+
+	Ok(Some(path))
+}
+
+/// Copy `path` to `backup`, creating any missing directories.
+pub fn backup(
+	path: &Path,
+	backup: &Path,
+	options: &Options,
+) -> Result<()> {
+	if let Some(parent) = backup.parent() {
+		fs::create_dir_all(parent).with_context(|| {
+			format!("Failed to create `{}`", parent.display())
+		})?;
+	}
+
+	let metadata = path
+		.symlink_metadata()
+		.context("Failed to get metadata for backup")?;
+
+	if metadata.is_symlink() {
+		let target = fs::read_link(path)?;
+		platform::symlink(path, backup, &target, options)
+	} else {
+		platform::copy(path, backup, options)
+	}
+	.with_context(|| {
+		format!(
+			"Failed to back up `{}` to `{}`",
+			path.display(),
+			backup.display()
+		)
+	})
+}
+
+fn append(path: &Path, suffix: &str) -> PathBuf {
+	let mut path = path.as_os_str().to_os_string();
+	path.push(suffix);
+	PathBuf::from(path)
+}
+
+/// Find the highest `N` of the existing `<base>.~N~` backups.
+fn highest_backup_number(base: &Path) -> Result<u64> {
+	let (parent, name) = match (base.parent(), base.file_name()) {
+		(Some(p), Some(n)) => (p, n.to_string_lossy()),
+		_ => return Ok(0),
+	};
+
+	let entries = match fs::read_dir(parent) {
+		Ok(v) => v,
+		Err(_) => return Ok(0),
+	};
+
+	let prefix = format!("{}.~", name);
+
+	let mut highest = 0;
+	for entry in entries {
+		let entry = entry?;
+		let entry_name = entry.file_name();
+
+		let n = entry_name
+			.to_str()
+			.and_then(|x| x.strip_prefix(&prefix))
+			.and_then(|x| x.strip_suffix('~'))
+			.and_then(|x| x.parse::<u64>().ok());
+
+		if let Some(n) = n {
+			highest = highest.max(n);
+		}
+	}
+
+	Ok(highest)
+}
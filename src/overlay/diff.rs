@@ -0,0 +1,258 @@
+use std::fs;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use colored::Colorize;
+use log::info;
+
+use super::{hash, platform, Entry};
+use crate::cli::Options;
+
+/// What installing a path would do to the destination.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Change {
+	/// The destination does not exist.
+	Create,
+	/// The destination exists and would be replaced.
+	Overwrite,
+	/// The destination exists and would be removed by a whiteout.
+	Remove,
+	/// The destination is already up to date.
+	Unchanged,
+	/// The destination is newer and `--update` was given.
+	SkipNewer,
+	/// The destination exists and `--no-clobber` was given.
+	SkipExisting,
+	/// One of the source and the destination is a directory
+	/// and the other is not.
+	Conflict,
+}
+
+impl Change {
+	/// Whether installing would modify the destination.
+	pub fn is_pending(self) -> bool {
+		matches!(
+			self,
+			Self::Create
+				| Self::Overwrite
+				| Self::Remove
+				| Self::Conflict
+		)
+	}
+
+	fn label(self) -> &'static str {
+		match self {
+			Self::Create => "create",
+			Self::Overwrite => "overwrite",
+			Self::Remove => "remove",
+			Self::Unchanged => "unchanged",
+			Self::SkipNewer => "skip-newer",
+			Self::SkipExisting => "skip-existing",
+			Self::Conflict => "conflict",
+		}
+	}
+}
+
+/// Decide what installing `entry` would do.
+///
+/// Without `compare_contents` every existing destination that
+/// is not skipped is assumed to need overwriting.
+pub fn classify(
+	entry: &Entry,
+	options: &Options,
+	compare_contents: bool,
+) -> Result<Change> {
+	let Entry { src, dst, target, whiteout, .. } = entry;
+
+	if *whiteout {
+		return Ok(match dst.symlink_metadata() {
+			Ok(_) => Change::Remove,
+			Err(_) => Change::Unchanged,
+		});
+	}
+
+	let src_metadata =
+		src.symlink_metadata().context("Failed to get metadata")?;
+
+	// symlinks replace whatever is at the destination, while
+	// everything else is installed through it
+	let dst_metadata = match target {
+		Some(_) => dst.symlink_metadata(),
+		None => dst.metadata(),
+	};
+
+	let dst_metadata = match dst_metadata {
+		Ok(v) => v,
+		Err(_) => return Ok(Change::Create),
+	};
+
+	if src_metadata.is_dir() != dst_metadata.is_dir() {
+		return Ok(Change::Conflict);
+	}
+
+	if options.no_overwrite {
+		return Ok(Change::SkipExisting);
+	}
+
+	if options.update {
+		let now = std::time::SystemTime::now();
+
+		let src_mtime = src_metadata.modified().unwrap_or(now);
+		let dst_mtime = dst_metadata.modified().unwrap_or(now);
+
+		if dst_mtime > src_mtime {
+			return Ok(Change::SkipNewer);
+		} else if dst_mtime == src_mtime {
+			return Ok(Change::Unchanged);
+		}
+	}
+
+	if !compare_contents {
+		return Ok(Change::Overwrite);
+	}
+
+	let unchanged = if let Some(target) = target {
+		dst_metadata.is_symlink() && &fs::read_link(dst)? == target
+	} else if src_metadata.is_dir() {
+		platform::mode(&src_metadata) == platform::mode(&dst_metadata)
+	} else {
+		platform::mode(&src_metadata) == platform::mode(&dst_metadata)
+			&& same_contents(entry, &src_metadata, &dst_metadata)?
+	};
+
+	if unchanged {
+		Ok(Change::Unchanged)
+	} else {
+		Ok(Change::Overwrite)
+	}
+}
+
+/// Whether the destination of `entry` already has the contents
+/// installing it would produce.
+pub(super) fn same_contents(
+	entry: &Entry,
+	src_metadata: &fs::Metadata,
+	dst_metadata: &fs::Metadata,
+) -> Result<bool> {
+	let same = match entry.render()? {
+		Some(rendered) => {
+			rendered.len() as u64 == dst_metadata.len()
+				&& hash::bytes(rendered.as_bytes())
+					== hash::file(&entry.dst)?
+		},
+		None => {
+			src_metadata.len() == dst_metadata.len()
+				&& hash::file(&entry.src)? == hash::file(&entry.dst)?
+		},
+	};
+
+	Ok(same)
+}
+
+/// Show what installing `entries` would change.
+///
+/// Returns whether any changes are pending.
+pub fn run(
+	entries: &[Entry],
+	options: &Options,
+	show_contents: bool,
+) -> Result<bool> {
+	let mut pending = false;
+
+	for entry in entries {
+		let change = classify(entry, options, true)
+			.with_context(|| format!("{}", entry.src.display()))?;
+
+		pending |= change.is_pending();
+
+		if options.machine_readable {
+			println!(
+				"{} {} {}",
+				change.label(),
+				entry.src.display(),
+				entry.dst.display()
+			);
+		} else {
+			let label = match change {
+				Change::Create => {
+					change.label().bold().bright_green()
+				},
+				Change::Overwrite => {
+					change.label().bold().bright_yellow()
+				},
+				Change::Remove | Change::Conflict => {
+					change.label().bold().bright_red()
+				},
+				_ => change.label().dimmed(),
+			};
+
+			info!(target: "no_fmt", "{:>12} {}", label, entry.dst.display());
+		}
+
+		if show_contents && change == Change::Overwrite {
+			show_content_diff(entry, options)?;
+		}
+	}
+
+	Ok(pending)
+}
+
+fn show_content_diff(entry: &Entry, options: &Options) -> Result<()> {
+	let (old, new) = (&entry.dst, &entry.src);
+
+	if !old.is_file() || !new.is_file() {
+		return Ok(());
+	}
+
+	let new_text = match entry.render()? {
+		Some(rendered) => Some(rendered),
+		None => read_text(new)?,
+	};
+
+	let (old_text, new_text) = match (read_text(old)?, new_text) {
+		(Some(a), Some(b)) => (a, b),
+		_ => {
+			println!(
+				"Binary files {} and {} differ",
+				old.display(),
+				new.display()
+			);
+			return Ok(());
+		},
+	};
+
+	let diff = similar::TextDiff::from_lines(&old_text, &new_text);
+	let diff = diff
+		.unified_diff()
+		.header(&old.to_string_lossy(), &new.to_string_lossy())
+		.to_string();
+
+	for line in diff.lines() {
+		if options.machine_readable {
+			println!("{}", line);
+			continue;
+		}
+
+		match line.chars().next() {
+			Some('+') => println!("{}", line.green()),
+			Some('-') => println!("{}", line.red()),
+			Some('@') => println!("{}", line.cyan()),
+			_ => println!("{}", line),
+		}
+	}
+
+	Ok(())
+}
+
+/// Read `path` as text, or `None` if it does not look like text.
+fn read_text(path: &Path) -> Result<Option<String>> {
+	let data = fs::read(path).with_context(|| {
+		format!("Failed to read `{}`", path.display())
+	})?;
+
+	if data.contains(&0) {
+		return Ok(None);
+	}
+
+	Ok(String::from_utf8(data).ok())
+}
@@ -0,0 +1,54 @@
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+
+use sha2::{Digest, Sha256};
+
+/// Compute the hex encoded SHA-256 digest of the contents of `path`.
+pub fn file(path: &Path) -> Result<String> {
+	let mut file =
+		fs::File::open(path).context("failed to open file")?;
+
+	let mut hasher = Sha256::new();
+	io::copy(&mut file, &mut hasher)
+		.context("failed to read file data")?;
+
+	Ok(hex(&hasher.finalize()))
+}
+
+/// Compute the hex encoded SHA-256 digest of `data`.
+pub fn bytes(data: &[u8]) -> String {
+	hex(&Sha256::digest(data))
+}
+
+fn hex(bytes: &[u8]) -> String {
+	bytes.iter().fold(
+		String::with_capacity(bytes.len() * 2),
+		|mut s, b| {
+			let _ = write!(s, "{:02x}", b);
+			s
+		},
+	)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn hex_encoding() {
+		assert_eq!(hex(&[]), "");
+		assert_eq!(hex(&[0x00, 0x0f, 0xa0, 0xff]), "000fa0ff");
+	}
+
+	#[test]
+	fn hash_bytes() {
+		assert_eq!(
+			bytes(b""),
+			"e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+		);
+	}
+}
@@ -1,14 +1,71 @@
-use std::collections::LinkedList;
+use std::collections::{HashMap, LinkedList};
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
-use anyhow::Result;
+use anyhow::{bail, Context, Result};
 
 use regex::{Regex, RegexBuilder};
 
+use crate::profile::expand::expand_pattern;
+use crate::profile::Profile;
+
+/// How the lines of an ignore file are interpreted.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Syntax {
+	/// Every line is a regex matched anywhere in the path.
+	#[default]
+	Regex,
+	/// Every line is a gitignore-style glob.
+	Glob,
+}
+
+impl Syntax {
+	/// Files named `*.glob` default to globs, everything else
+	/// to regex.
+	pub fn for_file(path: &Path) -> Self {
+		match path.extension() {
+			Some(x) if x == "glob" => Self::Glob,
+			_ => Self::Regex,
+		}
+	}
+
+	/// Parse a `syntax: <name>` header line.
+	fn from_header(line: &str) -> Option<Result<Self>> {
+		let name = line.strip_prefix("syntax:")?.trim();
+
+		Some(match name {
+			"regex" => Ok(Self::Regex),
+			"glob" => Ok(Self::Glob),
+			_ => Err(anyhow::anyhow!("Unknown syntax `{}`", name)),
+		})
+	}
+}
+
+#[derive(Debug)]
+struct Pattern {
+	regex: Regex,
+	/// Matches everything inside the paths matched by `regex`,
+	/// only used for globs
+	inside: Option<Regex>,
+	/// `dir/` only matches directories
+	dir_only: bool,
+	/// `!pattern` re-includes paths matched by earlier patterns
+	negated: bool,
+}
+
+impl Pattern {
+	fn matches(&self, path: &str, is_dir: bool) -> bool {
+		if self.inside.as_ref().is_some_and(|x| x.is_match(path)) {
+			return true;
+		}
+
+		self.regex.is_match(path) && (is_dir || !self.dir_only)
+	}
+}
+
 #[derive(Debug)]
 pub struct Ignore {
-	patterns: LinkedList<Regex>,
+	patterns: LinkedList<Pattern>,
 }
 
 impl Ignore {
@@ -16,46 +73,158 @@ impl Ignore {
 		Self { patterns: LinkedList::new() }
 	}
 
+	/// Add every pattern of `s`. Lines start out as `syntax`
+	/// and a `syntax: regex` or `syntax: glob` line switches
+	/// the lines after it.
+	///
+	/// With a `profile`, lines inside `[if ...]` and `[unless
+	/// ...]` sections only count if their condition holds and
+	/// variables in patterns are expanded.
 	pub fn add_from_str(
 		&mut self,
 		s: impl AsRef<str>,
+		mut syntax: Syntax,
+		profile: Option<&dyn Profile>,
 	) -> Result<usize> {
 		let mut patterns_added: usize = 0;
 
+		// whether each of the open sections is active
+		let mut sections: Vec<bool> = Vec::new();
+
 		for line in s.as_ref().lines().map(|x| x.trim()) {
 			// comments and empty lines
 			if line.starts_with('#') || line.is_empty() {
 				continue;
 			}
 
-			self.add_pattern(line)?;
+			if let Some(profile) = profile {
+				if line == "[end]" {
+					if sections.pop().is_none() {
+						bail!("Found `[end]` outside of a section");
+					}
+					continue;
+				}
+
+				if let Some(x) = condition(line, profile) {
+					sections.push(x?);
+					continue;
+				}
+			}
+
+			if sections.contains(&false) {
+				continue;
+			}
+
+			if let Some(x) = Syntax::from_header(line) {
+				syntax = x?;
+				continue;
+			}
+
+			match profile {
+				Some(profile) => {
+					let escape = match syntax {
+						Syntax::Regex => regex::escape,
+						Syntax::Glob => escape_glob,
+					};
+
+					let pattern =
+						expand_pattern(line, profile, escape)
+							.with_context(|| {
+								format!(
+									"Failed to expand pattern `{}`",
+									line
+								)
+							})?;
+
+					self.add_pattern(pattern, syntax)?;
+				},
+				None => self.add_pattern(line, syntax)?,
+			}
+
 			patterns_added = patterns_added.saturating_add(1);
 		}
 
+		if !sections.is_empty() {
+			bail!("Found a section without `[end]`");
+		}
+
 		Ok(patterns_added)
 	}
 
 	pub fn add_from_file(
 		&mut self,
 		file: impl AsRef<Path>,
+		profile: Option<&dyn Profile>,
 	) -> Result<usize> {
-		let contents = fs::read_to_string(file.as_ref())?;
-		self.add_from_str(&contents)
+		let file = file.as_ref();
+		let contents = fs::read_to_string(file)?;
+		self.add_from_str(&contents, Syntax::for_file(file), profile)
 	}
 
 	pub fn add_pattern(
 		&mut self,
 		pattern: impl AsRef<str>,
+		syntax: Syntax,
 	) -> Result<()> {
-		self.patterns
-			.push_back(Self::compile_pattern(pattern.as_ref())?);
+		let pattern = pattern.as_ref();
+
+		let (pattern, negated) = match pattern.strip_prefix('!') {
+			Some(x) => (x, true),
+			None => (pattern, false),
+		};
+
+		let pattern = match syntax {
+			Syntax::Regex => Pattern {
+				regex: Self::compile_pattern(pattern)?,
+				inside: None,
+				dir_only: false,
+				negated,
+			},
+			Syntax::Glob => {
+				let (regex, dir_only) = glob_to_regex(pattern)?;
+
+				Pattern {
+					regex: Self::compile_pattern(&format!(
+						"{}$",
+						regex
+					))?,
+					inside: Some(Self::compile_pattern(&format!(
+						"{}/",
+						regex
+					))?),
+					dir_only,
+					negated,
+				}
+			},
+		};
+
+		self.patterns.push_back(pattern);
 		Ok(())
 	}
 
-	pub fn matches(&self, path: impl AsRef<str>) -> bool {
+	pub fn matches(
+		&self,
+		path: impl AsRef<str>,
+		is_dir: bool,
+	) -> bool {
+		self.decide(path, is_dir).unwrap_or(false)
+	}
+
+	/// Whether `path` is ignored, or `None` if no pattern
+	/// matches it.
+	pub fn decide(
+		&self,
+		path: impl AsRef<str>,
+		is_dir: bool,
+	) -> Option<bool> {
 		let path = path.as_ref();
 
-		self.patterns.iter().any(|pattern| pattern.is_match(path))
+		// the last pattern that matches decides
+		self.patterns
+			.iter()
+			.rev()
+			.find(|pattern| pattern.matches(path, is_dir))
+			.map(|pattern| !pattern.negated)
 	}
 
 	fn compile_pattern(pattern: &str) -> Result<Regex> {
@@ -66,10 +235,402 @@ impl Ignore {
 	}
 }
 
+/// Parse an `[if VAR]`, `[if VAR=value]`, `[unless VAR]` or
+/// `[unless VAR=value]` line and check whether it holds.
+fn condition(
+	line: &str,
+	profile: &dyn Profile,
+) -> Option<Result<bool>> {
+	let line = line.strip_prefix('[')?.strip_suffix(']')?;
+	let (keyword, expr) = line.split_once(char::is_whitespace)?;
+
+	let negated = match keyword {
+		"if" => false,
+		"unless" => true,
+		_ => return None,
+	};
+
+	let holds = match expr.split_once('=') {
+		Some((name, value)) => {
+			profile.get(name.trim()).map(|x| x == Some(value.trim()))
+		},
+		None => profile
+			.get(expr.trim())
+			.map(|x| x.is_some_and(|x| !x.is_empty())),
+	};
+
+	Some(holds.map(|x| x != negated))
+}
+
+/// Escape everything in `s` that has a meaning in globs.
+fn escape_glob(s: &str) -> String {
+	let mut ret = String::with_capacity(s.len());
+
+	for c in s.chars() {
+		if matches!(c, '*' | '?' | '[' | ']' | '\\') {
+			ret.push('\\');
+		}
+		ret.push(c);
+	}
+
+	ret
+}
+
+/// Everything that decides whether a path of an overlay is
+/// ignored.
+#[derive(Debug)]
+pub struct Rules {
+	src_root: PathBuf,
+	/// Patterns that can not be negated
+	defaults: Ignore,
+	/// Patterns of the whole overlay
+	ignore: Ignore,
+	/// Name of the ignore files inside the overlay
+	nested_file: &'static str,
+}
+
+impl Rules {
+	pub fn new(
+		src_root: &Path,
+		defaults: Ignore,
+		ignore: Ignore,
+		nested_file: &'static str,
+	) -> Self {
+		Self {
+			src_root: src_root.to_path_buf(),
+			defaults,
+			ignore,
+			nested_file,
+		}
+	}
+
+	/// Start keeping track of the ignore files inside the
+	/// overlay for a walk.
+	pub fn nested<'a>(
+		&self,
+		profile: &'a dyn Profile,
+	) -> Result<Nested<'a>> {
+		let mut nested = Nested::new(self.nested_file, profile);
+		nested.enter(Path::new("/"), &self.src_root)?;
+		Ok(nested)
+	}
+
+	/// Whether `path` is ignored, with `nested` holding the
+	/// ignore files of the directories it is in.
+	///
+	/// Ignore files inside the overlay take precedence over the
+	/// patterns of the whole overlay.
+	pub fn decide(
+		&self,
+		nested: &mut Nested,
+		path: &Path,
+		is_dir: bool,
+	) -> bool {
+		nested.leave(path);
+
+		let files =
+			nested.stack.iter().rev().map(|(x, y)| (x.as_path(), y));
+		self.decide_with(files, path, is_dir)
+	}
+
+	/// Whether `path` or any directory it is in is ignored,
+	/// without walking the overlay.
+	///
+	/// The ignore files inside the overlay are kept in `cache`,
+	/// so each of them is only read once.
+	pub fn matches(
+		&self,
+		path: &Path,
+		is_dir: bool,
+		profile: &dyn Profile,
+		cache: &mut NestedCache,
+	) -> Result<bool> {
+		let mut dir = PathBuf::from("/");
+		cache.load(
+			&dir,
+			&self.src_root,
+			self.nested_file,
+			profile,
+		)?;
+
+		let mut components = path
+			.strip_prefix("/")
+			.unwrap_or(path)
+			.components()
+			.peekable();
+
+		while let Some(component) = components.next() {
+			let path = dir.join(component);
+			let last = components.peek().is_none();
+
+			// the ignore files of every directory `path` is in,
+			// the deepest one first
+			let files = path
+				.ancestors()
+				.skip(1)
+				.filter_map(|x| Some((x, cache.get(x)?)));
+
+			if self.decide_with(files, &path, is_dir || !last) {
+				return Ok(true);
+			}
+
+			if !last {
+				let src = self.src_root.join(path.strip_prefix("/")?);
+				cache.load(&path, &src, self.nested_file, profile)?;
+			}
+
+			dir = path;
+		}
+
+		Ok(false)
+	}
+
+	/// Whether `path` is ignored, with `files` being the ignore
+	/// files of the directories it is in, the deepest one first.
+	fn decide_with<'a>(
+		&self,
+		files: impl Iterator<Item = (&'a Path, &'a Ignore)>,
+		path: &Path,
+		is_dir: bool,
+	) -> bool {
+		let path_str = path.to_string_lossy();
+
+		self.defaults.matches(&path_str, is_dir)
+			|| decide_nested(files, path, is_dir).unwrap_or_else(
+				|| self.ignore.matches(&path_str, is_dir),
+			)
+	}
+}
+
+/// Whether `path` is ignored by `files`, the ignore files of
+/// the directories it is in, the deepest one deciding first.
+/// `None` if no pattern matches it.
+fn decide_nested<'a>(
+	mut files: impl Iterator<Item = (&'a Path, &'a Ignore)>,
+	path: &Path,
+	is_dir: bool,
+) -> Option<bool> {
+	files.find_map(|(dir, ignore)| {
+		let rel_path =
+			Path::new("/").join(path.strip_prefix(dir).ok()?);
+		ignore.decide(rel_path.to_string_lossy(), is_dir)
+	})
+}
+
+/// Load the ignore file `file_name` of `dir`, if it has one.
+fn load_nested(
+	dir: &Path,
+	file_name: &str,
+	profile: &dyn Profile,
+) -> Result<Option<Ignore>> {
+	let path = dir.join(file_name);
+	if !path.exists() {
+		return Ok(None);
+	}
+
+	// just like .gitignore files they default to globs
+	let mut ignore = Ignore::empty();
+	fs::read_to_string(&path)
+		.map_err(anyhow::Error::from)
+		.and_then(|x| {
+			ignore.add_from_str(x, Syntax::Glob, Some(profile))
+		})
+		.with_context(|| {
+			format!("Failed to read ignore file `{}`", path.display())
+		})?;
+
+	Ok(Some(ignore))
+}
+
+/// The ignore files inside an overlay by the directory they
+/// are in, with a leading `/`, for deciding about paths in any
+/// order.
+#[derive(Debug, Default)]
+pub struct NestedCache {
+	files: HashMap<PathBuf, Option<Ignore>>,
+}
+
+impl NestedCache {
+	fn load(
+		&mut self,
+		rel_path: &Path,
+		dir: &Path,
+		file_name: &str,
+		profile: &dyn Profile,
+	) -> Result<()> {
+		if !self.files.contains_key(rel_path) {
+			let ignore = load_nested(dir, file_name, profile)?;
+			self.files.insert(rel_path.to_path_buf(), ignore);
+		}
+
+		Ok(())
+	}
+
+	fn get(&self, rel_path: &Path) -> Option<&Ignore> {
+		self.files.get(rel_path)?.as_ref()
+	}
+}
+
+/// Ignore files found in the directories of a walk, each one
+/// matching paths relative to its own directory.
+pub struct Nested<'a> {
+	file_name: &'static str,
+	profile: &'a dyn Profile,
+	stack: Vec<(PathBuf, Ignore)>,
+}
+
+impl<'a> Nested<'a> {
+	pub fn new(
+		file_name: &'static str,
+		profile: &'a dyn Profile,
+	) -> Self {
+		Self { file_name, profile, stack: Vec::new() }
+	}
+
+	/// Load the ignore file of `dir` if it has one. `rel_path`
+	/// is where `dir` is in the walk, with a leading `/`.
+	///
+	/// Directories must be entered in walk order.
+	pub fn enter(
+		&mut self,
+		rel_path: &Path,
+		dir: &Path,
+	) -> Result<()> {
+		if let Some(ignore) =
+			load_nested(dir, self.file_name, self.profile)?
+		{
+			self.stack.push((rel_path.to_path_buf(), ignore));
+		}
+
+		Ok(())
+	}
+
+	/// Forget the ignore files of the directories the walk has
+	/// left to get to `path`.
+	fn leave(&mut self, path: &Path) {
+		while self
+			.stack
+			.last()
+			.is_some_and(|(dir, _)| !path.starts_with(dir))
+		{
+			self.stack.pop();
+		}
+	}
+}
+
+/// Translate a gitignore-style glob into a regex that matches
+/// the start of paths with a leading `/`, along with whether it
+/// only matches directories.
+fn glob_to_regex(glob: &str) -> Result<(String, bool)> {
+	let (glob, dir_only) = match glob.strip_suffix('/') {
+		Some(x) => (x, true),
+		None => (glob, false),
+	};
+
+	// like gitignore, a / anywhere but at the end anchors the
+	// pattern to the root, otherwise it matches at any depth
+	let anchored = glob.contains('/');
+	let glob = glob.strip_prefix('/').unwrap_or(glob);
+
+	if glob.is_empty() {
+		bail!("Empty glob pattern");
+	}
+
+	let mut regex = String::from(if anchored {
+		"^/"
+	} else {
+		"^.*/"
+	});
+
+	let chars: Vec<char> = glob.chars().collect();
+	let mut i = 0;
+
+	while i < chars.len() {
+		match chars[i] {
+			'*' if chars.get(i + 1) == Some(&'*') => {
+				let mut end = i;
+				while chars.get(end) == Some(&'*') {
+					end += 1;
+				}
+
+				let at_start = i == 0 || chars[i - 1] == '/';
+				match chars.get(end) {
+					// `**/` matches zero or more directories
+					Some('/') if at_start => {
+						regex.push_str("(?:.*/)?");
+						end += 1;
+					},
+					// a trailing `/**` matches everything inside
+					None if at_start => regex.push_str(".+"),
+					_ => regex.push_str("[^/]*"),
+				}
+
+				i = end;
+				continue;
+			},
+			'*' => regex.push_str("[^/]*"),
+			'?' => regex.push_str("[^/]"),
+			'[' => match class_to_regex(&chars[i..]) {
+				Some((class, len)) => {
+					regex.push_str(&class);
+					i += len;
+					continue;
+				},
+				None => regex.push_str(r"\["),
+			},
+			'\\' if i + 1 < chars.len() => {
+				i += 1;
+				regex.push_str(&regex::escape(
+					chars[i].encode_utf8(&mut [0; 4]),
+				));
+			},
+			c => regex
+				.push_str(&regex::escape(c.encode_utf8(&mut [0; 4]))),
+		}
+
+		i += 1;
+	}
+
+	Ok((regex, dir_only))
+}
+
+/// Translate the character class at the start of `chars`,
+/// returning it along with how many characters it used.
+fn class_to_regex(chars: &[char]) -> Option<(String, usize)> {
+	let mut class = String::from("[");
+	let mut i = 1;
+
+	if matches!(chars.get(i), Some('!' | '^')) {
+		class.push('^');
+		i += 1;
+	}
+
+	let start = i;
+	loop {
+		match chars.get(i)? {
+			// a ] right at the start is part of the class
+			']' if i != start => break,
+			'/' => return None,
+			c @ ('\\' | '[' | ']' | '&' | '~' | '^') => {
+				class.push('\\');
+				class.push(*c);
+			},
+			c => class.push(*c),
+		}
+
+		i += 1;
+	}
+
+	class.push(']');
+	Some((class, i + 1))
+}
+
 #[cfg(test)]
 mod tests {
 	use super::*;
 
+	use std::collections::HashMap;
+
 	#[test]
 	fn parse_patterns() {
 		let patterns = r"
@@ -83,30 +644,189 @@ mod tests {
 		";
 
 		let mut ignore = Ignore::empty();
-		ignore.add_from_str(patterns).unwrap();
-
-		assert!(ignore.matches("/file"));
-		assert!(!ignore.matches("/dir/file"));
-		assert!(!ignore.matches("file"));
-
-		assert!(!ignore.matches("/dir"));
-		assert!(ignore.matches("/dir/file1.ignore"));
-		assert!(ignore.matches("/dir/file2.ignore"));
-		assert!(ignore.matches("/dir/another_file.ignore"));
-		assert!(!ignore.matches("/dir/test.txt"));
-
-		assert!(ignore.matches("/dir/0-a.tar"));
-		assert!(!ignore.matches("/dir/0-A.tar"));
-		assert!(!ignore.matches("/dir/test.tar"));
-		assert!(ignore.matches("/dir/0-a.tar.t"));
-		assert!(ignore.matches("/dir/0-a.tar.e"));
-
-		assert!(!ignore.matches("/dir1"));
-		assert!(!ignore.matches("/dir/dir1"));
-		assert!(!ignore.matches("/dir/dir"));
-		assert!(ignore.matches("/dir/dir/"));
-		assert!(ignore.matches("/dir/dir/test_file"));
-
-		assert!(!ignore.matches("some_random_file"));
+		ignore.add_from_str(patterns, Syntax::Regex, None).unwrap();
+
+		assert!(ignore.matches("/file", false));
+		assert!(!ignore.matches("/dir/file", false));
+		assert!(!ignore.matches("file", false));
+
+		assert!(!ignore.matches("/dir", true));
+		assert!(ignore.matches("/dir/file1.ignore", false));
+		assert!(ignore.matches("/dir/file2.ignore", false));
+		assert!(ignore.matches("/dir/another_file.ignore", false));
+		assert!(!ignore.matches("/dir/test.txt", false));
+
+		assert!(ignore.matches("/dir/0-a.tar", false));
+		assert!(!ignore.matches("/dir/0-A.tar", false));
+		assert!(!ignore.matches("/dir/test.tar", false));
+		assert!(ignore.matches("/dir/0-a.tar.t", false));
+		assert!(ignore.matches("/dir/0-a.tar.e", false));
+
+		assert!(!ignore.matches("/dir1", true));
+		assert!(!ignore.matches("/dir/dir1", true));
+		assert!(!ignore.matches("/dir/dir", true));
+		assert!(ignore.matches("/dir/dir/", true));
+		assert!(ignore.matches("/dir/dir/test_file", false));
+
+		assert!(!ignore.matches("some_random_file", false));
+	}
+
+	#[test]
+	fn parse_globs() {
+		let patterns = r"
+syntax: glob
+
+*.o
+/build
+cache/
+doc/**/*.md
+src/**
+lib?.[!a-c]
+/opt/*
+\#hash
+		";
+
+		let mut ignore = Ignore::empty();
+		ignore.add_from_str(patterns, Syntax::Regex, None).unwrap();
+
+		assert!(ignore.matches("/main.o", false));
+		assert!(ignore.matches("/dir/main.o", false));
+		assert!(!ignore.matches("/main.os", false));
+		assert!(!ignore.matches("/main_o", false));
+
+		assert!(ignore.matches("/build", true));
+		assert!(ignore.matches("/build/file", false));
+		assert!(!ignore.matches("/dir/build", true));
+
+		assert!(ignore.matches("/cache", true));
+		assert!(ignore.matches("/dir/cache", true));
+		assert!(ignore.matches("/dir/cache/file", false));
+		assert!(!ignore.matches("/cache", false));
+
+		assert!(ignore.matches("/doc/a.md", false));
+		assert!(ignore.matches("/doc/a/b/c.md", false));
+		assert!(!ignore.matches("/doc/a.txt", false));
+		assert!(!ignore.matches("/dir/doc/a.md", false));
+
+		assert!(ignore.matches("/src/a/b", false));
+		assert!(!ignore.matches("/src", true));
+
+		assert!(ignore.matches("/dir/a.o", true));
+		assert!(!ignore.matches("/dir", true));
+
+		assert!(ignore.matches("/opt/app", true));
+		assert!(ignore.matches("/opt/app/file", false));
+		assert!(!ignore.matches("/opt", true));
+
+		assert!(ignore.matches("/lib1.d", false));
+		assert!(!ignore.matches("/lib1.a", false));
+		assert!(!ignore.matches("/lib12.d", false));
+
+		assert!(ignore.matches("/#hash", false));
+	}
+
+	#[test]
+	fn negated_patterns() {
+		let patterns = r"
+syntax: glob
+
+/docs/**
+!/docs/man
+*.bak
+!keep.bak
+/docs/man/old
+		";
+
+		let mut ignore = Ignore::empty();
+		ignore.add_from_str(patterns, Syntax::Regex, None).unwrap();
+
+		assert!(!ignore.matches("/docs", true));
+		assert!(ignore.matches("/docs/index.html", false));
+		assert!(ignore.matches("/docs/html", true));
+		assert!(!ignore.matches("/docs/man", true));
+		assert!(!ignore.matches("/docs/man/man1/foo.1", false));
+		assert!(ignore.matches("/docs/man/old", true));
+		assert!(ignore.matches("/docs/man/old/foo.1", false));
+
+		assert!(ignore.matches("/dir/file.bak", false));
+		assert!(!ignore.matches("/dir/keep.bak", false));
+	}
+
+	#[test]
+	fn conditional_sections() {
+		let patterns = r"
+[if ARCH=aarch64]
+/lib/x86_64
+[end]
+
+[unless FEATURE_X]
+/feature_x
+[end]
+
+[if FEATURE_Y]
+/feature_y$
+[unless ARCH=aarch64]
+/feature_y_{ARCH}
+[end]
+[end]
+
+syntax: glob
+*.{ARCH}
+		";
+
+		let mut profile: HashMap<String, String> = HashMap::new();
+		profile.insert("ARCH".to_string(), "aarch64".to_string());
+		profile.insert("FEATURE_Y".to_string(), "1".to_string());
+
+		let mut ignore = Ignore::empty();
+		ignore
+			.add_from_str(patterns, Syntax::Regex, Some(&profile))
+			.unwrap();
+
+		assert!(ignore.matches("/lib/x86_64", true));
+		assert!(ignore.matches("/feature_x", true));
+		assert!(ignore.matches("/feature_y", true));
+		assert!(!ignore.matches("/feature_y_aarch64", true));
+		assert!(ignore.matches("/dir/file.aarch64", false));
+		assert!(!ignore.matches("/dir/file.x86_64", false));
+
+		profile.insert("FEATURE_X".to_string(), "1".to_string());
+		profile.remove("FEATURE_Y");
+		profile.insert("ARCH".to_string(), "x86_64".to_string());
+
+		let mut ignore = Ignore::empty();
+		ignore
+			.add_from_str(patterns, Syntax::Regex, Some(&profile))
+			.unwrap();
+
+		assert!(!ignore.matches("/lib/x86_64", true));
+		assert!(!ignore.matches("/feature_x", true));
+		assert!(!ignore.matches("/feature_y", true));
+		assert!(ignore.matches("/dir/file.x86_64", false));
+
+		// unbalanced sections
+		let mut ignore = Ignore::empty();
+		assert!(ignore
+			.add_from_str(
+				"[if ARCH]\n/a",
+				Syntax::Glob,
+				Some(&profile)
+			)
+			.is_err());
+		assert!(ignore
+			.add_from_str("/a\n[end]", Syntax::Glob, Some(&profile))
+			.is_err());
+	}
+
+	#[test]
+	fn syntax_from_file_name() {
+		assert_eq!(
+			Syntax::for_file(Path::new(".turboinstall/ignore")),
+			Syntax::Regex
+		);
+		assert_eq!(
+			Syntax::for_file(Path::new(".turboinstall/ignore.glob")),
+			Syntax::Glob
+		);
 	}
 }
@@ -0,0 +1,270 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use colored::Colorize;
+use log::{error, info, warn};
+
+use super::{hash, platform};
+use crate::cli::Options;
+
+#[derive(
+	Debug,
+	Clone,
+	Copy,
+	PartialEq,
+	Eq,
+	serde::Serialize,
+	serde::Deserialize,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum EntryKind {
+	File,
+	Dir,
+	Symlink,
+}
+
+/// A single destination path touched by an install.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Entry {
+	pub path: PathBuf,
+	pub kind: EntryKind,
+	pub mode: u32,
+	/// Whether the path did not exist before the install.
+	pub created: bool,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub hash: Option<String>,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub target: Option<PathBuf>,
+}
+
+/// The list of destination paths created or overwritten by an install.
+#[derive(Debug, Default)]
+pub struct Manifest {
+	entries: BTreeMap<PathBuf, Entry>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct RawManifest {
+	version: u32,
+	entries: Vec<Entry>,
+}
+
+const MANIFEST_VERSION: u32 = 1;
+
+impl Manifest {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	pub fn load(path: &Path) -> Result<Self> {
+		let raw = fs::read_to_string(path).with_context(|| {
+			format!("Unable to read manifest '{}'", path.display())
+		})?;
+
+		let raw: RawManifest = serde_json::from_str(&raw)
+			.context("Unable to parse manifest")?;
+
+		if raw.version != MANIFEST_VERSION {
+			bail!("Unsupported manifest version {}", raw.version)
+		}
+
+		Ok(Self {
+			entries: raw
+				.entries
+				.into_iter()
+				.map(|x| (x.path.clone(), x))
+				.collect(),
+		})
+	}
+
+	pub fn save(&self, path: &Path) -> Result<()> {
+		let raw = RawManifest {
+			version: MANIFEST_VERSION,
+			entries: self.entries.values().cloned().collect(),
+		};
+
+		let raw = serde_json::to_string_pretty(&raw)
+			.context("Unable to serialize manifest")?;
+
+		fs::write(path, raw).with_context(|| {
+			format!("Unable to write manifest '{}'", path.display())
+		})?;
+
+		Ok(())
+	}
+
+	/// Get the manifest entry for the current state of `path`.
+	pub fn entry(path: &Path, created: bool) -> Result<Entry> {
+		let metadata =
+			path.symlink_metadata().with_context(|| {
+				format!("Failed to get `{}` metadata", path.display())
+			})?;
+
+		let (kind, hash, target) = if metadata.is_symlink() {
+			let target = fs::read_link(path).with_context(|| {
+				format!("Failed to read link `{}`", path.display())
+			})?;
+			(EntryKind::Symlink, None, Some(target))
+		} else if metadata.is_dir() {
+			(EntryKind::Dir, None, None)
+		} else {
+			let hash = hash::file(path).with_context(|| {
+				format!("Failed to hash `{}`", path.display())
+			})?;
+			(EntryKind::File, Some(hash), None)
+		};
+
+		Ok(Entry {
+			path: path.to_path_buf(),
+			kind,
+			mode: platform::mode(&metadata),
+			created,
+			hash,
+			target,
+		})
+	}
+
+	/// Add `entry` to the manifest.
+	///
+	/// A path that is added more than once keeps its original
+	/// `created` flag, so a file created by one overlay and then
+	/// overwritten by another is still known to be created.
+	pub fn insert(&mut self, mut entry: Entry) {
+		if let Some(existing) = self.entries.get(&entry.path) {
+			entry.created = existing.created;
+		}
+
+		self.entries.insert(entry.path.clone(), entry);
+	}
+
+	/// Forget about `path` and everything inside it.
+	pub fn remove(&mut self, path: &Path) {
+		self.entries.retain(|x, _| !x.starts_with(path));
+	}
+
+	/// Remove every recorded path.
+	///
+	/// Entries are processed in reverse order so the contents of
+	/// a directory are always removed before the directory itself.
+	/// Directories are only removed if they were created by the
+	/// install and are empty.
+	pub fn uninstall(
+		&self,
+		options: &Options,
+		force: bool,
+	) -> Result<()> {
+		for entry in self.entries.values().rev() {
+			let r = remove_entry(entry, options, force)
+				.with_context(|| format!("{}", entry.path.display()));
+
+			if options.no_abort {
+				if let Err(e) = r {
+					error!("{} {:#}", "[Silent]".dimmed().white(), e);
+				}
+			} else {
+				r?
+			}
+		}
+
+		Ok(())
+	}
+}
+
+fn remove_entry(
+	entry: &Entry,
+	options: &Options,
+	force: bool,
+) -> Result<()> {
+	let path = &entry.path;
+
+	let metadata = match path.symlink_metadata() {
+		Ok(v) => v,
+		Err(_) => {
+			warn!("`{}` does not exist", path.display());
+			return Ok(());
+		},
+	};
+
+	match entry.kind {
+		EntryKind::Dir => {
+			if !metadata.is_dir() {
+				bail!("`{}` is no longer a directory", path.display())
+			}
+
+			if !entry.created {
+				return Ok(());
+			}
+
+			let is_empty = fs::read_dir(path)
+				.context("Failed to read directory")?
+				.next()
+				.is_none();
+
+			if !is_empty {
+				warn!("Not removing non-empty `{}`", path.display());
+				return Ok(());
+			}
+
+			if !options.dry_run {
+				fs::remove_dir(path)
+					.context("Failed to remove directory")?;
+			}
+		},
+		EntryKind::File => {
+			if metadata.is_dir() {
+				bail!("`{}` is now a directory", path.display())
+			}
+
+			if !force {
+				if let Some(expected) = &entry.hash {
+					let actual = hash::file(path)?;
+
+					if &actual != expected {
+						warn!(
+							"Not removing modified `{}`",
+							path.display()
+						);
+						return Ok(());
+					}
+				}
+			}
+
+			if !options.dry_run {
+				fs::remove_file(path)
+					.context("Failed to remove file")?;
+			}
+		},
+		EntryKind::Symlink => {
+			if !metadata.is_symlink() {
+				bail!("`{}` is no longer a symlink", path.display())
+			}
+
+			if !force && entry.target.is_some() {
+				let actual = fs::read_link(path)?;
+
+				if entry.target.as_ref() != Some(&actual) {
+					warn!(
+						"Not removing modified `{}`",
+						path.display()
+					);
+					return Ok(());
+				}
+			}
+
+			if !options.dry_run {
+				fs::remove_file(path)
+					.context("Failed to remove symlink")?;
+			}
+		},
+	}
+
+	if options.machine_readable {
+		println!("{}", path.display());
+	} else {
+		info!(target: "no_fmt", "{:>12} {}", "Removing".bold().bright_red(), path.display());
+	}
+
+	Ok(())
+}
@@ -0,0 +1,217 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use colored::Colorize;
+use log::{error, info};
+
+use super::{ignore, Entry, Overlay, Session};
+use crate::cli::Options;
+use crate::profile;
+
+/// The paths of the destination an overlay leaves alone.
+#[derive(Debug)]
+pub struct Ignored {
+	rules: ignore::Rules,
+	profile: Arc<profile::Resolved>,
+	/// Where the directories of the overlay are inside of it,
+	/// by where they are installed
+	dirs: HashMap<PathBuf, PathBuf>,
+	nested: ignore::NestedCache,
+}
+
+impl Ignored {
+	pub fn new(
+		overlay: &Overlay,
+		profile: Arc<profile::Resolved>,
+		entries: &[Entry],
+		options: &Options,
+	) -> Result<Self> {
+		let rules =
+			overlay.ignore_rules(profile.as_ref(), options)?;
+
+		let dirs = entries
+			.iter()
+			.filter(|x| {
+				!x.whiteout && x.target.is_none() && x.src.is_dir()
+			})
+			.map(|x| (x.dst.clone(), x.rel_path.clone()))
+			.collect();
+
+		Ok(Self { rules, profile, dirs, nested: Default::default() })
+	}
+
+	/// Whether the overlay ignores `path`, going by where it
+	/// would be inside the overlay.
+	fn matches(&mut self, path: &Path, is_dir: bool) -> Result<bool> {
+		for dir in path.ancestors().skip(1) {
+			if let Some(rel_path) = self.dirs.get(dir) {
+				let rel_path = rel_path.join(path.strip_prefix(dir)?);
+				return self.rules.matches(
+					&rel_path,
+					is_dir,
+					self.profile.as_ref(),
+					&mut self.nested,
+				);
+			}
+		}
+
+		Ok(false)
+	}
+}
+
+/// Remove every path under the directories provided by
+/// `entries` that none of them provided.
+///
+/// Paths matching `--ignore` or `--protect`, or ignored by any
+/// of the overlays, are kept along with the directories
+/// containing them. The destination root itself is never
+/// cleaned.
+pub fn delete_extraneous(
+	dst_root: &Path,
+	entries: &[Entry],
+	ignored: &mut [Ignored],
+	options: &Options,
+	session: &mut Session,
+) -> Result<()> {
+	let mut protect = ignore::Ignore::empty();
+	for pattern in options
+		.ignore_patterns
+		.iter()
+		.chain(options.protect_patterns.iter())
+	{
+		protect
+			.add_pattern(pattern, ignore::Syntax::Regex)
+			.with_context(|| {
+				format!("Failed to compile pattern `{}`", pattern)
+			})?;
+	}
+
+	let provided: HashSet<&Path> = entries
+		.iter()
+		.filter(|x| !x.whiteout)
+		.map(|x| x.dst.as_path())
+		.collect();
+
+	let dirs: BTreeSet<&Path> = entries
+		.iter()
+		.filter(|x| {
+			!x.whiteout && x.target.is_none() && x.src.is_dir()
+		})
+		.map(|x| x.dst.as_path())
+		.collect();
+
+	let backup_dir = session
+		.transaction
+		.as_ref()
+		.map(|x| x.backup_dir().to_path_buf());
+
+	// nested directories are handled along with their parent
+	for dir in dirs
+		.iter()
+		.filter(|x| !x.ancestors().skip(1).any(|y| dirs.contains(y)))
+	{
+		if !dir.is_dir() {
+			continue;
+		}
+
+		let mut kept: HashSet<PathBuf> = HashSet::new();
+
+		for entry in walkdir::WalkDir::new(dir)
+			.min_depth(1)
+			.contents_first(true)
+			.sort_by_file_name()
+		{
+			let entry = entry?;
+			let path = entry.path();
+
+			let rel_path =
+				Path::new("/").join(path.strip_prefix(dst_root)?);
+
+			let keep = provided.contains(path)
+				|| session.backups.iter().any(|x| x == path)
+				|| kept.contains(path)
+				|| protect.matches(
+					rel_path.to_string_lossy(),
+					entry.file_type().is_dir(),
+				) || backup_dir
+				.as_ref()
+				.is_some_and(|x| path.starts_with(x));
+
+			// Ok(true) if the path is kept
+			let r = if keep {
+				Ok(true)
+			} else {
+				let is_dir = entry.file_type().is_dir();
+
+				ignored
+					.iter_mut()
+					.try_fold(false, |acc, x| -> Result<bool> {
+						Ok(acc || x.matches(path, is_dir)?)
+					})
+					.and_then(|x| {
+						if !x {
+							delete(path, options, session)?;
+						}
+						Ok(x)
+					})
+					.with_context(|| format!("{}", path.display()))
+			};
+
+			if !matches!(r, Ok(false)) {
+				kept.extend(
+					path.ancestors()
+						.skip(1)
+						.take_while(|x| x != dir)
+						.map(|x| x.to_path_buf()),
+				);
+			}
+
+			if options.no_abort {
+				if let Err(e) = r {
+					error!("{} {:#}", "[Silent]".dimmed().white(), e);
+				}
+			} else {
+				r?;
+			}
+		}
+	}
+
+	Ok(())
+}
+
+fn delete(
+	path: &Path,
+	options: &Options,
+	session: &mut Session,
+) -> Result<()> {
+	if !options.dry_run {
+		if let Some(transaction) = &mut session.transaction {
+			transaction.remove(path)?;
+		}
+
+		// everything inside has already been removed
+		if let Ok(metadata) = path.symlink_metadata() {
+			if metadata.is_dir() {
+				fs::remove_dir(path)
+			} else {
+				fs::remove_file(path)
+			}
+			.context("Failed to remove path")?;
+		}
+
+		if let Some(manifest) = &mut session.manifest {
+			manifest.remove(path);
+		}
+	}
+
+	if options.machine_readable {
+		println!("{}", path.display());
+	} else {
+		info!(target: "no_fmt", "{:>12} {}", "Removing".bold().bright_red(), path.display());
+	}
+
+	Ok(())
+}
@@ -1,19 +1,50 @@
-use std::ops::Deref;
-use std::path::{Path, PathBuf};
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
 
 use anyhow::{bail, Context, Result};
 use colored::Colorize;
 use log::{error, info, warn};
 
 use crate::cli::Options;
-use crate::profile::Profile;
+use crate::profile::expand::{self, expand_path};
+use crate::profile::{self, Profile};
 
+pub mod backup;
+pub mod diff;
+mod hash;
 mod ignore;
+pub mod manifest;
+pub mod mirror;
+mod parallel;
 pub mod platform;
+pub mod transaction;
+pub mod verify;
 
-static DEFAULT_IGNORE_FILES: &[&str] = &[".turboinstall/ignore"];
+static DEFAULT_IGNORE_FILES: &[&str] =
+	&[".turboinstall/ignore", ".turboinstall/ignore.glob"];
 
-static DEFAULT_IGNORE_PATTERNS: &[&str] = &["^/.turboinstall"];
+static DEFAULT_IGNORE_PATTERNS: &[&str] =
+	&["^/.turboinstall", r"/\.turboinstallignore$"];
+
+/// Ignore files that apply to the directory they are in.
+static NESTED_IGNORE_FILE: &str = ".turboinstallignore";
+
+static NOEXPAND_FILE: &str = ".turboinstall/noexpand";
+
+/// The profile of the overlay itself, the first one found is used.
+static PROFILE_FILES: &[&str] = &[
+	".turboinstall/profile.json",
+	".turboinstall/profile.toml",
+	".turboinstall/profile.yaml",
+	".turboinstall/profile.env",
+];
+
+static TEMPLATES_FILE: &str = ".turboinstall/templates";
+
+static TEMPLATE_SUFFIX: &str = ".tmpl";
+
+static WHITEOUT_PREFIX: &str = ".wh.";
 
 #[derive(Debug, Clone, PartialEq, Eq, clap::ValueEnum)]
 pub enum HookType {
@@ -30,6 +61,66 @@ impl HookType {
 	}
 }
 
+/// State shared by all overlays installed in one run.
+#[derive(Debug, Default)]
+pub struct Session {
+	pub manifest: Option<manifest::Manifest>,
+	pub transaction: Option<transaction::Transaction>,
+	/// Backups of overwritten files made so far.
+	pub backups: Vec<PathBuf>,
+}
+
+/// A path of an overlay and where it should be installed.
+#[derive(Debug)]
+pub struct Entry {
+	pub src: PathBuf,
+	/// Where `src` is inside its overlay, with a leading `/`.
+	pub rel_path: PathBuf,
+	pub dst: PathBuf,
+	/// The target to create the symlink with, if `src` is a symlink.
+	pub target: Option<PathBuf>,
+	/// Whether `src` is a whiteout and `dst` should be removed.
+	pub whiteout: bool,
+	/// The profile to render `src` with, if it is a template.
+	pub template: Option<Arc<profile::Resolved>>,
+}
+
+impl Entry {
+	/// Get the contents of `src` with the profile variables
+	/// expanded, if it is a template.
+	pub fn render(&self) -> Result<Option<String>> {
+		let profile = match &self.template {
+			Some(v) => v,
+			None => return Ok(None),
+		};
+
+		fs::read_to_string(&self.src)
+			.context("Failed to read template")
+			.and_then(|x| {
+				expand::expand_template(&x, profile.as_ref())
+			})
+			.map(Some)
+			.with_context(|| {
+				format!(
+					"Failed to render template `{}`",
+					self.src.display()
+				)
+			})
+	}
+}
+
+/// An entry that is going to be installed.
+#[derive(Debug)]
+struct Step<'a> {
+	entry: &'a Entry,
+	is_dir: bool,
+	/// Paths that will be created by installing the entry,
+	/// starting from the entry itself.
+	created: Vec<PathBuf>,
+	/// The outcome of the install, once it has been performed.
+	result: Option<Result<Vec<manifest::Entry>>>,
+}
+
 #[derive(Debug)]
 pub struct Overlay {
 	src_root: PathBuf,
@@ -79,22 +170,60 @@ impl Overlay {
 		Ok(Self { src_root: src, dst_root: dst })
 	}
 
-	pub fn install(
-		&mut self,
-		profile: &dyn Profile,
-		options: &Options,
-	) -> Result<()> {
-		let mut ignore = ignore::Ignore::empty();
+	/// Get the profile to install the overlay with.
+	///
+	/// The variables of `global` override those of the overlay's
+	/// own profile, if it has one. References to other variables
+	/// are resolved only when they are used, after merging, so
+	/// overriding a variable also changes every value that
+	/// refers to it.
+	pub fn profile(
+		&self,
+		global: &profile::Resolved,
+	) -> Result<Arc<profile::Resolved>> {
+		let mut ret = profile::Resolved::default();
 
-		// default ignores
-		for pattern in DEFAULT_IGNORE_PATTERNS
+		if let Some(path) = PROFILE_FILES
 			.iter()
-			.map(|x| x.deref())
-			.chain(options.ignore_patterns.iter().map(|x| x.as_str()))
+			.map(|x| self.src_root.join(x))
+			.find(|x| x.exists())
 		{
-			ignore.add_pattern(pattern).with_context(|| {
-				format!("Failed to compile pattern `{}`", pattern)
-			})?;
+			let vars = profile::load_layered(&path, None)
+				.with_context(|| {
+					format!(
+						"Failed to load overlay profile `{}`",
+						path.display()
+					)
+				})?;
+
+			ret.extend(vars);
+		}
+
+		ret.extend_from(global);
+
+		Ok(Arc::new(ret))
+	}
+
+	/// Get everything that decides which paths of the overlay
+	/// are ignored.
+	fn ignore_rules(
+		&self,
+		profile: &dyn Profile,
+		options: &Options,
+	) -> Result<ignore::Rules> {
+		// default ignores, these can not be negated
+		let mut defaults = ignore::Ignore::empty();
+		for pattern in DEFAULT_IGNORE_PATTERNS {
+			defaults.add_pattern(pattern, ignore::Syntax::Regex)?;
+		}
+
+		let mut ignore = ignore::Ignore::empty();
+		for pattern in &options.ignore_patterns {
+			ignore
+				.add_pattern(pattern, ignore::Syntax::Regex)
+				.with_context(|| {
+					format!("Failed to compile pattern `{}`", pattern)
+				})?;
 		}
 
 		// load ignore files if they exists
@@ -108,25 +237,117 @@ impl Overlay {
 				let ignore_path = self.src_root.join(ignore_path);
 
 				if ignore_path.exists() {
-					ignore.add_from_file(&ignore_path).with_context(
-						|| {
+					ignore
+						.add_from_file(&ignore_path, Some(profile))
+						.with_context(|| {
 							format!(
 								"Failed to read ignore file `{}`",
 								ignore_path.display()
 							)
-						},
-					)?;
+						})?;
 				}
 			}
 		}
 
-		walkdir::WalkDir::new(&self.src_root)
+		Ok(ignore::Rules::new(
+			&self.src_root,
+			defaults,
+			ignore,
+			NESTED_IGNORE_FILE,
+		))
+	}
+
+	/// Get every path of the overlay that is not ignored along
+	/// with where it should be installed, in walk order.
+	pub fn entries(
+		&self,
+		profile: &Arc<profile::Resolved>,
+		options: &Options,
+	) -> Result<Vec<Entry>> {
+		let rules = self.ignore_rules(profile.as_ref(), options)?;
+
+		// paths matching these are installed without expansion
+		let mut noexpand = ignore::Ignore::empty();
+
+		let noexpand_path = self.src_root.join(NOEXPAND_FILE);
+		if noexpand_path.exists() {
+			noexpand
+				.add_from_file(&noexpand_path, Some(profile.as_ref()))
+				.with_context(|| {
+					format!(
+						"Failed to read noexpand file `{}`",
+						noexpand_path.display()
+					)
+				})?;
+		}
+
+		// files matching these have their contents expanded
+		let mut templates = ignore::Ignore::empty();
+
+		for pattern in &options.template_patterns {
+			templates
+				.add_pattern(pattern, ignore::Syntax::Glob)
+				.with_context(|| {
+					format!("Failed to compile pattern `{}`", pattern)
+				})?;
+		}
+
+		let templates_path = self.src_root.join(TEMPLATES_FILE);
+		if templates_path.exists() {
+			templates
+				.add_from_file(
+					&templates_path,
+					Some(profile.as_ref()),
+				)
+				.with_context(|| {
+					format!(
+						"Failed to read templates file `{}`",
+						templates_path.display()
+					)
+				})?;
+		}
+
+		// ignore files inside the overlay
+		let mut nested = rules.nested(profile.as_ref())?;
+
+		// the walk itself can not fail, so this is set when a
+		// nested ignore file can not be loaded
+		let mut nested_error = None;
+
+		let entries = walkdir::WalkDir::new(&self.src_root)
 			// dont return self.src again
 			.min_depth(1)
 			.contents_first(false)
-			.follow_links(false)
+			.follow_links(options.dereference)
 			.sort_by_file_name()
 			.into_iter()
+			// ignored directories are not descended into
+			.filter_entry(|x| {
+				let rel_path = match x.path().strip_prefix(&self.src_root) {
+					Ok(x) if x.as_os_str().is_empty() => return true,
+					Ok(x) => x,
+					Err(_) => return false,
+				};
+				let is_dir = x.file_type().is_dir();
+
+				// we add a / in front of the relative path
+				// so we can use the leading / to match files
+				// in the root of the overlay
+				let absolute_path = Path::new("/").join(rel_path);
+
+				if rules.decide(&mut nested, &absolute_path, is_dir) {
+					return false;
+				}
+
+				if is_dir {
+					if let Err(e) = nested.enter(&absolute_path, x.path()) {
+						nested_error.get_or_insert(e);
+						return false;
+					}
+				}
+
+				true
+			})
 			// filter out all the problem entries
 			.filter_map(|x| x.ok())
 			.filter_map(|x| {
@@ -136,125 +357,497 @@ impl Overlay {
 					.map(|x| x.to_path_buf())
 					.ok()
 			})
-			.filter(|x| {
-				// we add a / in front of the relative path
-				// so we can use the leading / to match files
-				// in the root of the overlay
-				let absolute_path = Path::new("/").join(x);
-				!ignore.matches(absolute_path.to_string_lossy())
-			}).try_for_each(|src_rel_path| -> Result<()> {
-				let src = self.get_src_path(&src_rel_path).with_context(|| format!("Failed to resolve source path `{}`", src_rel_path.display()))?;
-				let dst = self.get_dst_path(&src_rel_path, profile).with_context(|| format!("Failed to resolve path `{}`", src_rel_path.display()))?;
-
-				let r = self.install_path(&src, &dst, options).with_context(|| format!("{}", src.display()));
+			.map(|src_rel_path| -> Result<Entry> {
+				let src = self.get_src_path(&src_rel_path, options).with_context(|| format!("Failed to resolve source path `{}`", src_rel_path.display()))?;
+				let src_metadata = src.symlink_metadata()?;
 
-				if options.no_abort {
-					if let Err(e) = r {
-						error!("{} {:#}", "[Silent]".dimmed().white(), e);
-					}
+				// `.wh.<name>` removes `<name>` from the destination
+				let whiteout_name = if src_metadata.is_dir() {
+					None
 				} else {
-					r?
+					src_rel_path
+						.file_name()
+						.and_then(|x| x.to_str())
+						.and_then(|x| x.strip_prefix(WHITEOUT_PREFIX))
+						.filter(|x| !x.is_empty())
+				};
+
+				if let Some("." | "..") = whiteout_name {
+					bail!("Whiteout `{}` does not name a path", src_rel_path.display())
 				}
 
-				Ok(())
+				let whiteout = whiteout_name.map(|x| src_rel_path.with_file_name(x));
+
+				let is_template = whiteout.is_none()
+					&& src_metadata.is_file()
+					&& templates.matches(Path::new("/").join(&src_rel_path).to_string_lossy(), false);
+
+				// a `<name>.tmpl` template is installed as `<name>`
+				let template_name = src_rel_path
+					.file_name()
+					.and_then(|x| x.to_str())
+					.and_then(|x| x.strip_suffix(TEMPLATE_SUFFIX))
+					.filter(|x| !x.is_empty());
+
+				let dst_rel_path = match (&whiteout, template_name) {
+					(Some(x), _) => x.clone(),
+					(None, Some(x)) if is_template => src_rel_path.with_file_name(x),
+					_ => src_rel_path.clone(),
+				};
+				let dst = self.get_dst_path(&dst_rel_path, src_metadata.is_dir(), profile.as_ref(), &noexpand).with_context(|| format!("Failed to resolve path `{}`", src_rel_path.display()))?;
+
+				if !is_inside(&self.dst_root, &dst) {
+					bail!("`{}` is installed to `{}`, which is not inside the destination", src_rel_path.display(), dst.display())
+				}
+
+				let target = if whiteout.is_none() && src_metadata.is_symlink() {
+					Some(get_link_target(&src, profile.as_ref(), options).with_context(|| format!("{}", src.display()))?)
+				} else {
+					None
+				};
+
+				// templates are only rendered once they are needed
+				let template = is_template.then(|| profile.clone());
+
+				Ok(Entry { src, rel_path: Path::new("/").join(&src_rel_path), dst, target, whiteout: whiteout.is_some(), template })
 			})
+			.collect::<Result<Vec<_>>>();
+
+		match nested_error {
+			Some(e) => Err(e),
+			None => entries,
+		}
+	}
+
+	/// Install every entry of the overlay and return them.
+	pub fn install(
+		&mut self,
+		profile: &Arc<profile::Resolved>,
+		options: &Options,
+		session: &mut Session,
+	) -> Result<Vec<Entry>> {
+		let entries = self.entries(profile, options)?;
+		let parallel = options.jobs.get() > 1;
+
+		let mut steps = Vec::new();
+
+		for entry in &entries {
+			match self.prepare(entry, options, session) {
+				Ok(Some(mut step)) => {
+					// directories are always created in order so they
+					// exist before anything is installed inside them,
+					// and the same goes for removing paths
+					if !parallel || step.is_dir || entry.whiteout {
+						step.result = Some(self.perform(
+							&step,
+							options,
+							session.manifest.is_some(),
+						));
+					}
+
+					let failed = matches!(step.result, Some(Err(_)));
+					steps.push(Ok(step));
+
+					if failed && !options.no_abort {
+						break;
+					}
+				},
+				Ok(None) => {},
+				Err(e) => {
+					steps.push(Err(e.context(format!(
+						"{}",
+						entry.src.display()
+					))));
+
+					if !options.no_abort {
+						break;
+					}
+				},
+			}
+
+			if !parallel {
+				self.finish(&mut steps, options, session)?;
+			}
+		}
+
+		if parallel {
+			let record = session.manifest.is_some();
+
+			let pending: Vec<&mut Step> = steps
+				.iter_mut()
+				.filter_map(|x| x.as_mut().ok())
+				.filter(|x| x.result.is_none())
+				.collect();
+
+			parallel::for_each(pending, options.jobs.get(), |step| {
+				let r = self.perform(step, options, record);
+				let keep_going = r.is_ok() || options.no_abort;
+
+				step.result = Some(r);
+				keep_going
+			});
+		}
+
+		self.finish(&mut steps, options, session)?;
+
+		Ok(entries)
 	}
 
-	fn get_src_path(&self, src_rel_path: &Path) -> Result<PathBuf> {
-		let src = self.src_root.join(src_rel_path).canonicalize()?;
-		Ok(src)
+	fn get_src_path(
+		&self,
+		src_rel_path: &Path,
+		options: &Options,
+	) -> Result<PathBuf> {
+		let src = self.src_root.join(src_rel_path);
+
+		// symlinks are only resolved when dereferencing, otherwise
+		// the link itself is the source
+		if options.dereference {
+			Ok(src.canonicalize()?)
+		} else {
+			src.symlink_metadata()?;
+			Ok(src)
+		}
 	}
 
 	fn get_dst_path(
 		&self,
 		src_rel_path: &Path,
+		is_dir: bool,
 		profile: &dyn Profile,
+		noexpand: &ignore::Ignore,
 	) -> Result<PathBuf> {
+		// everything from the first component that is inside a
+		// noexpand subtree is kept as is
+		let mut prefix = PathBuf::new();
+		let mut components = src_rel_path.components();
+
+		while let Some(component) = components.next() {
+			let path = Path::new("/").join(&prefix).join(component);
+
+			// only the last component can be something other
+			// than a directory
+			let component_is_dir =
+				is_dir || components.clone().next().is_some();
+
+			if noexpand
+				.matches(path.to_string_lossy(), component_is_dir)
+			{
+				let literal =
+					Path::new(component.as_os_str()).join(components);
+
+				let dst_rel_path = expand_path(&prefix, profile)?;
+				return Ok(self
+					.dst_root
+					.join(dst_rel_path)
+					.join(literal));
+			}
+
+			prefix.push(component);
+		}
+
 		let dst_rel_path = expand_path(src_rel_path, profile)?;
 		let dst = self.dst_root.join(dst_rel_path);
 		Ok(dst)
 	}
 
-	fn install_path(
+	/// Decide whether `entry` needs to be installed and save
+	/// anything that is about to be modified.
+	fn prepare<'a>(
 		&self,
-		src: &Path,
-		dst: &Path,
+		entry: &'a Entry,
 		options: &Options,
-	) -> Result<()> {
-		let src_metadata =
-			src.metadata().context("Failed to get metadata")?;
+		session: &mut Session,
+	) -> Result<Option<Step<'a>>> {
+		let Entry { src, dst, target, .. } = entry;
+
+		let src_metadata = src
+			.symlink_metadata()
+			.context("Failed to get metadata")?;
+
+		// every path between the destination root and `dst` that
+		// does not exist yet will be created by this install
+		let created: Vec<PathBuf> = dst
+			.ancestors()
+			.take_while(|x| {
+				*x != self.dst_root && x.symlink_metadata().is_err()
+			})
+			.map(|x| x.to_path_buf())
+			.collect();
+
+		match diff::classify(entry, options, options.checksum)? {
+			diff::Change::Create | diff::Change::Overwrite => {},
+			diff::Change::Unchanged => return Ok(None),
+			diff::Change::Remove => {
+				if !options.dry_run {
+					if let Some(transaction) =
+						&mut session.transaction
+					{
+						transaction.remove(dst)?;
+					}
+				}
 
-		if dst.exists() {
-			if options.no_overwrite {
+				return Ok(Some(Step {
+					entry,
+					is_dir: false,
+					created: Vec::new(),
+					result: None,
+				}));
+			},
+			diff::Change::SkipExisting => {
 				warn!(
 					"Not overwriting existing destination `{}`",
 					dst.display()
 				);
-				return Ok(());
-			}
-
-			let dst_metadata = dst.metadata().with_context(|| {
-				format!(
-					"Failed to get destination `{}` metadata",
+				return Ok(None);
+			},
+			diff::Change::SkipNewer => {
+				warn!("Destination `{}` is newer", dst.display(),);
+				return Ok(None);
+			},
+			diff::Change::Conflict => {
+				bail!(
+					"Destination `{}` is a different type of file",
 					dst.display()
 				)
-			})?;
+			},
+		}
+
+		let is_dir = target.is_none() && src_metadata.is_dir();
 
-			if options.update {
-				let now = std::time::SystemTime::now();
+		if created.first() != Some(dst) && !is_dir {
+			self.backup(entry, options, session)?;
+		}
 
-				let src_mtime =
-					src_metadata.modified().unwrap_or(now);
-				let dst_mtime =
-					dst_metadata.modified().unwrap_or(now);
+		if !options.dry_run {
+			if let Some(transaction) = &mut session.transaction {
+				for path in created.iter().rev() {
+					transaction.create(path);
+				}
 
-				if dst_mtime > src_mtime {
-					warn!("Destination `{}` is newer", dst.display(),);
-					return Ok(());
-				} else if dst_mtime == src_mtime {
-					return Ok(());
+				if created.first() != Some(dst) {
+					transaction
+						.backup(&replaced_path(entry, options))?;
 				}
 			}
 		}
 
+		Ok(Some(Step { entry, is_dir, created, result: None }))
+	}
+
+	/// Keep a copy of what installing `entry` overwrites, if
+	/// any of the backup options were given.
+	fn backup(
+		&self,
+		entry: &Entry,
+		options: &Options,
+		session: &mut Session,
+	) -> Result<()> {
+		let dst = &entry.dst;
+
+		let backup = match backup::backup_path(
+			&self.dst_root,
+			dst,
+			options,
+		)? {
+			Some(v) => v,
+			None => return Ok(()),
+		};
+
 		if !options.dry_run {
-			if src.is_dir() {
-				platform::create_dir_all(src, dst, options)
-					.with_context(|| {
+			if let Some(transaction) = &mut session.transaction {
+				if backup.symlink_metadata().is_ok() {
+					transaction.backup(&backup)?;
+				} else {
+					let created: Vec<&Path> = backup
+						.ancestors()
+						.take_while(|x| {
+							!x.as_os_str().is_empty()
+								&& x.symlink_metadata().is_err()
+						})
+						.collect();
+
+					for path in created.iter().rev() {
+						transaction.create(path);
+					}
+				}
+			}
+
+			// the backup is named after `dst`, but has the
+			// contents of the file that is actually replaced
+			backup::backup(
+				&replaced_path(entry, options),
+				&backup,
+				options,
+			)?;
+		}
+
+		if !options.machine_readable {
+			info!(target: "no_fmt", "{:>12} {} {} {}", "Backing up".bold().bright_yellow(), dst.display(), "to".bold().bright_cyan(), backup.display());
+		}
+
+		session.backups.push(backup);
+
+		Ok(())
+	}
+
+	/// Install a prepared entry.
+	///
+	/// This does not touch any shared state, so it can be called
+	/// from multiple threads. Returns the manifest entries for
+	/// the installed paths if `record` is set.
+	fn perform(
+		&self,
+		step: &Step,
+		options: &Options,
+		record: bool,
+	) -> Result<Vec<manifest::Entry>> {
+		let Entry { src, dst, target, whiteout, .. } = step.entry;
+
+		if options.dry_run {
+			return Ok(Vec::new());
+		}
+
+		if *whiteout {
+			if !is_inside(&self.dst_root, dst) {
+				bail!(
+					"Refusing to remove `{}` outside of the destination",
+					dst.display()
+				)
+			}
+
+			// the transaction might have already moved it
+			if let Ok(metadata) = dst.symlink_metadata() {
+				if metadata.is_dir() {
+					fs::remove_dir_all(dst)
+				} else {
+					fs::remove_file(dst)
+				}
+				.with_context(|| {
+					format!("Failed to remove `{}`", dst.display())
+				})?;
+			}
+
+			return Ok(Vec::new());
+		}
+
+		if let Some(target) = target {
+			platform::symlink(src, dst, target, options)
+				.with_context(|| {
+					format!(
+						"Failed to create symlink `{}`",
+						dst.display()
+					)
+				})?;
+		} else if step.is_dir {
+			platform::create_dir_all(src, dst, options)
+				.with_context(|| {
+					format!(
+						"Failed to create directory `{}`",
+						dst.display()
+					)
+				})?;
+		} else if let Some(rendered) = step.entry.render()? {
+			platform::write(src, dst, rendered.as_bytes(), options)
+				.with_context(|| {
+				format!("Failed to install to `{}`", dst.display())
+			})?;
+		} else {
+			if options.hard_link {
+				platform::hard_link(src, dst, options).with_context(
+					|| {
 						format!(
-							"Failed to create directory `{}`",
+							"Failed to hard link to `{}`",
 							dst.display()
 						)
-					})?;
+					},
+				)?
 			} else {
-				if options.hard_link {
-					platform::hard_link(src, dst, options)
-						.with_context(|| {
-							format!(
-								"Failed to hard link to `{}`",
-								dst.display()
-							)
-						})?
+				platform::copy(src, dst, options).with_context(
+					|| {
+						format!(
+							"Failed to install to `{}`",
+							dst.display()
+						)
+					},
+				)?;
+			}
+		}
+
+		let mut recorded = Vec::new();
+
+		if record {
+			for path in step.created.iter().rev() {
+				recorded.push(manifest::Manifest::entry(path, true)?);
+			}
+
+			if step.created.first() != Some(dst) {
+				recorded.push(manifest::Manifest::entry(dst, false)?);
+			}
+		}
+
+		Ok(recorded)
+	}
+
+	/// Record and report all performed steps in order.
+	///
+	/// With `--no-abort` errors are only logged, otherwise the
+	/// first error is returned after all other steps have been
+	/// recorded, so the manifest knows about everything that
+	/// was actually installed.
+	fn finish(
+		&self,
+		steps: &mut Vec<Result<Step>>,
+		options: &Options,
+		session: &mut Session,
+	) -> Result<()> {
+		let mut first_error = None;
+
+		for step in steps.drain(..) {
+			let r = step.and_then(|step| {
+				let Entry { src, dst, whiteout, .. } = step.entry;
+
+				// steps are not performed after an earlier one
+				// failed, unless given --no-abort
+				let recorded = match step.result {
+					Some(r) => {
+						r.with_context(|| format!("{}", src.display()))?
+					},
+					None => return Ok(()),
+				};
+
+				if let Some(manifest) = &mut session.manifest {
+					if *whiteout {
+						manifest.remove(dst);
+					}
+
+					recorded.into_iter().for_each(|x| manifest.insert(x));
+				}
+
+				if options.machine_readable {
+					println!("{} {}", src.display(), dst.display());
+				} else if *whiteout {
+					info!(target: "no_fmt", "{:>12} {}", "Removing".bold().bright_red(), dst.display());
 				} else {
-					platform::copy(src, dst, options).with_context(
-						|| {
-							format!(
-								"Failed to install to `{}`",
-								dst.display()
-							)
-						},
-					)?;
+					info!(target: "no_fmt", "{:>12} {} {} {}", "Installing".bold().bright_green(), src.display(), "to".bold().bright_cyan(), dst.display());
+				}
+
+				Ok(())
+			});
+
+			if let Err(e) = r {
+				if options.no_abort {
+					error!("{} {:#}", "[Silent]".dimmed().white(), e);
+				} else if first_error.is_none() {
+					first_error = Some(e);
 				}
 			}
 		}
 
-		if options.machine_readable {
-			println!("{} {}", src.display(), dst.display());
-		} else {
-			info!(target: "no_fmt", "{:>12} {} {} {}", "Installing".bold().bright_green(), src.display(), "to".bold().bright_cyan(), dst.display());
+		match first_error {
+			Some(e) => Err(e),
+			None => Ok(()),
 		}
-
-		Ok(())
 	}
 
 	pub fn run_hooks(
@@ -310,6 +903,17 @@ impl Overlay {
 				command.arg(&self.src_root).arg(&self.dst_root);
 
 				for (k, v) in profile.list() {
+					// nested keys can not be used from shell
+					// scripts, so they are also exported with
+					// underscores
+					if k.contains('.') {
+						let alias = k.replace('.', "_");
+
+						if profile.var(&alias).is_none() {
+							command.env(alias, &v);
+						}
+					}
+
 					command.env(k, v);
 				}
 
@@ -344,132 +948,51 @@ impl Overlay {
 	}
 }
 
-fn expand_vars(s: &str, profile: &dyn Profile) -> Result<String> {
-	let mut ret = s.to_string();
-
-	loop {
-		let start = ret.find('{');
-		let end = ret.find('}');
-
-		if !(start.is_some() && end.is_some()) {
-			break;
-		}
-
-		if end <= start {
-			break;
-		}
-
-		let start = start.expect("the None should have been handled by the above if statements");
-		let end = end.expect("the None should have been handled by the above if statements");
-
-		let var_name = &ret[start.saturating_add(1)..end];
-
-		if let Some(value) = profile.var(var_name) {
-			if value.is_empty() {
-				bail!("Found empty variable.")
-			}
-
-			ret = format!(
-				"{}{}{}",
-				&ret[..start],
-				value,
-				&ret[end.saturating_add(1)..]
-			);
-		} else {
-			bail!("Variable '{}' not found in profile.", var_name)
-		}
+/// Whether `path` is strictly below `root`, without going
+/// through any `..`.
+fn is_inside(root: &Path, path: &Path) -> bool {
+	match path.strip_prefix(root) {
+		Ok(rel_path) => {
+			rel_path.components().next().is_some()
+				&& rel_path
+					.components()
+					.all(|x| matches!(x, Component::Normal(_)))
+		},
+		Err(_) => false,
 	}
-
-	Ok(ret)
 }
 
-fn expand_path(
-	p: impl AsRef<Path>,
-	profile: &dyn Profile,
-) -> Result<PathBuf> {
-	let mut path = PathBuf::new();
-
-	for component in p
-		.as_ref()
-		.components()
-		.map(|x| x.as_os_str().to_string_lossy())
-	{
-		let expanded = expand_vars(&component, profile)?;
-
-		let expanded =
-			expanded.strip_prefix('/').unwrap_or(&expanded);
-
-		path.push(expanded);
+/// Get the path that installing `entry` replaces.
+///
+/// Copied and rendered files are written through symlinks at
+/// the destination, so they replace the final target of the
+/// link instead of `dst` itself.
+fn replaced_path(entry: &Entry, options: &Options) -> PathBuf {
+	let writes_through = entry.target.is_none()
+		&& !entry.src.is_dir()
+		&& (entry.template.is_some() || !options.hard_link);
+
+	if writes_through {
+		platform::resolve(&entry.dst)
+	} else {
+		entry.dst.clone()
 	}
-
-	Ok(path)
 }
 
-#[cfg(test)]
-mod tests {
-	use super::*;
-
-	#[test]
-	fn expand_vars_tests() {
-		use std::collections::HashMap;
-
-		impl Profile for HashMap<String, String> {
-			fn var(&self, s: &str) -> Option<&str> {
-				self.get(s).map(|x| x.as_str())
-			}
-
-			fn list(&self) -> Vec<(String, String)> {
-				self.iter()
-					.map(|(k, v)| (k.clone(), v.clone()))
-					.collect()
-			}
-		}
-
-		let mut dummy_profile: HashMap<String, String> =
-			HashMap::new();
-		dummy_profile
-			.insert("var1".to_string(), "variable 1".to_string());
-		dummy_profile
-			.insert("VAR2".to_string(), "VARIABLE 2".to_string());
-		dummy_profile
-			.insert("space var".to_string(), " spaced ".to_string());
-
-		assert_eq!(
-			expand_vars("..{var1}..", &dummy_profile).unwrap(),
-			"..variable 1.."
-		);
-
-		assert_eq!(
-			expand_vars("{var1}..", &dummy_profile).unwrap(),
-			"variable 1.."
-		);
-
-		assert_eq!(
-			expand_vars("..{var1}", &dummy_profile).unwrap(),
-			"..variable 1"
-		);
-
-		assert_eq!(
-			expand_vars("{VAR2}", &dummy_profile).unwrap(),
-			"VARIABLE 2"
-		);
-
-		assert_eq!(
-			expand_vars("..{space var}..", &dummy_profile).unwrap(),
-			".. spaced .."
-		);
-
-		assert_eq!(
-			expand_vars("{var1} {VAR2}", &dummy_profile).unwrap(),
-			"variable 1 VARIABLE 2"
-		);
-
-		assert_eq!(
-			expand_vars("}var1{", &dummy_profile).unwrap(),
-			"}var1{"
-		);
-
-		assert!(expand_vars("{}{var1}{}", &dummy_profile).is_err());
-		assert!(expand_vars("{}}var1{{}", &dummy_profile).is_err());
+/// Get the target of the symlink `src` as it should be
+/// created at the destination.
+fn get_link_target(
+	src: &Path,
+	profile: &dyn Profile,
+	options: &Options,
+) -> Result<PathBuf> {
+	let target = fs::read_link(src).context("Failed to read link")?;
+
+	if options.expand_link_targets && target.is_absolute() {
+		let target = expand_path(&target, profile)
+			.context("Failed to expand link target")?;
+		Ok(Path::new("/").join(target))
+	} else {
+		Ok(target)
 	}
 }
@@ -0,0 +1,42 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+/// Call `f` on every item using up to `jobs` threads.
+///
+/// Items are handed out in order, but may complete in any order.
+/// Once `f` returns `false` no new items are handed out, the
+/// ones already started are still completed.
+pub fn for_each<T, F>(items: Vec<T>, jobs: usize, f: F)
+where
+	T: Send,
+	F: Fn(T) -> bool + Sync,
+{
+	let jobs = jobs.min(items.len());
+	let queue = Mutex::new(items.into_iter());
+	let stop = AtomicBool::new(false);
+
+	thread::scope(|s| {
+		for _ in 0..jobs {
+			s.spawn(|| loop {
+				if stop.load(Ordering::Relaxed) {
+					break;
+				}
+
+				let item = match queue.lock() {
+					Ok(mut queue) => queue.next(),
+					Err(_) => None,
+				};
+
+				match item {
+					Some(item) => {
+						if !f(item) {
+							stop.store(true, Ordering::Relaxed);
+						}
+					},
+					None => break,
+				}
+			});
+		}
+	});
+}
@@ -0,0 +1,151 @@
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use anyhow::{Context, Result};
+#[cfg(unix)]
+use log::warn;
+
+use crate::cli::Options;
+
+static TEMP_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// Create `path` by letting `create` write a temporary sibling
+/// and then renaming it into place, so nobody ever sees a
+/// partially written `path`.
+///
+/// `create` is expected to set up all attributes of the file
+/// before it is renamed.
+pub fn replace(
+	path: &Path,
+	options: &Options,
+	create: impl FnOnce(&Path) -> Result<()>,
+) -> Result<()> {
+	let temp_path = temp_path(path);
+
+	let r = create(&temp_path)
+		.and_then(|_| {
+			if options.fsync {
+				sync_file(&temp_path)?;
+			}
+
+			fs::rename(&temp_path, path)
+				.context("failed to move file into place")
+		})
+		.and_then(|_| match path.parent() {
+			Some(parent) if options.fsync => sync_dir(parent),
+			_ => Ok(()),
+		});
+
+	if r.is_err() {
+		let _ = fs::remove_file(&temp_path);
+	}
+
+	r
+}
+
+/// Get the path that writing through `path` would modify.
+///
+/// Regular files are installed through symlinks at the
+/// destination, so the temporary file has to be renamed
+/// over the final target of the link instead.
+pub fn resolve(path: &Path) -> PathBuf {
+	match path.symlink_metadata() {
+		Ok(x) if x.is_symlink() => fs::canonicalize(path)
+			.unwrap_or_else(|_| path.to_path_buf()),
+		_ => path.to_path_buf(),
+	}
+}
+
+/// Give `temp_path` the owner of the `path` it is about to
+/// replace, as a renamed file belongs to whoever created it.
+#[cfg(unix)]
+pub fn keep_owner(path: &Path, temp_path: &Path) -> Result<()> {
+	use std::os::unix::fs::{lchown, MetadataExt};
+
+	// nothing is being replaced
+	let metadata = match path.symlink_metadata() {
+		Ok(x) => x,
+		Err(_) => return Ok(()),
+	};
+
+	let temp_metadata = temp_path.symlink_metadata()?;
+	if metadata.uid() == temp_metadata.uid()
+		&& metadata.gid() == temp_metadata.gid()
+	{
+		return Ok(());
+	}
+
+	match lchown(
+		temp_path,
+		Some(metadata.uid()),
+		Some(metadata.gid()),
+	) {
+		Ok(_) => {},
+		// only root can give files away
+		Err(e)
+			if e.kind() == std::io::ErrorKind::PermissionDenied =>
+		{
+			warn!(
+				"Unable to keep the owner of `{}`: {}",
+				path.display(),
+				e
+			);
+			return Ok(());
+		},
+		Err(e) => return Err(e).context("failed to keep ownership"),
+	}
+
+	// changing the owner clears the setuid and setgid bits
+	if temp_metadata.is_file() {
+		fs::set_permissions(temp_path, temp_metadata.permissions())
+			.context("failed to preserve permissions")?;
+	}
+
+	Ok(())
+}
+
+// files have no owner to keep on other platforms
+#[cfg(not(unix))]
+pub fn keep_owner(_: &Path, _: &Path) -> Result<()> {
+	Ok(())
+}
+
+fn temp_path(path: &Path) -> PathBuf {
+	let mut name = OsString::from(".");
+	name.push(path.file_name().unwrap_or_default());
+	name.push(format!(
+		".turboinstall-tmp.{}.{}",
+		std::process::id(),
+		TEMP_COUNTER.fetch_add(1, Ordering::Relaxed)
+	));
+
+	path.with_file_name(name)
+}
+
+fn sync_file(path: &Path) -> Result<()> {
+	// symlinks can not be opened, their contents are flushed
+	// along with the directory
+	if !path.symlink_metadata()?.is_file() {
+		return Ok(());
+	}
+
+	// fsync works on read-only files too
+	fs::File::open(path)
+		.and_then(|x| x.sync_all())
+		.context("failed to sync file")
+}
+
+#[cfg(unix)]
+fn sync_dir(path: &Path) -> Result<()> {
+	fs::File::open(path)
+		.and_then(|x| x.sync_all())
+		.context("failed to sync directory")
+}
+
+// directories can not be synced on other platforms
+#[cfg(not(unix))]
+fn sync_dir(_: &Path) -> Result<()> {
+	Ok(())
+}
@@ -3,6 +3,7 @@ use std::path::Path;
 
 use anyhow::{Context, Result};
 
+use super::atomic;
 use crate::cli::Options;
 
 #[derive(Debug, clap::Args)]
@@ -22,27 +23,84 @@ pub fn create_dir_all(
 	Ok(())
 }
 
-// the `remove_file_if_exists` handle the fact that `fs::hard_link` fails
-// if dst already exists
-// and that trying to copy after linking will not remove
-// the link
+// everything is created next to `dst` and then renamed over
+// it, which also takes care of `fs::hard_link` failing if
+// dst already exists
 
-pub fn hard_link(src: &Path, dst: &Path, _: &Options) -> Result<()> {
-	let _ = remove_file_if_exists(dst);
-	fs::hard_link(src, dst)?;
-	Ok(())
+pub fn hard_link(
+	src: &Path,
+	dst: &Path,
+	options: &Options,
+) -> Result<()> {
+	atomic::replace(dst, options, |temp| {
+		fs::hard_link(src, temp)?;
+		Ok(())
+	})
 }
 
-pub fn copy(src: &Path, dst: &Path, _: &Options) -> Result<()> {
-	let _ = remove_file_if_exists(dst);
-	fs::copy(src, dst)?;
-	Ok(())
+pub fn copy(src: &Path, dst: &Path, options: &Options) -> Result<()> {
+	let dst = atomic::resolve(dst);
+
+	atomic::replace(&dst, options, |temp| {
+		fs::copy(src, temp)?;
+		atomic::keep_owner(&dst, temp)
+	})
+}
+
+pub fn write(
+	src: &Path,
+	dst: &Path,
+	contents: &[u8],
+	options: &Options,
+) -> Result<()> {
+	let src_metadata = src.metadata()?;
+	let dst = atomic::resolve(dst);
+
+	atomic::replace(&dst, options, |temp| {
+		fs::write(temp, contents)?;
+
+		fs::set_permissions(temp, src_metadata.permissions())
+			.context("failed to preserve permissions")?;
+
+		atomic::keep_owner(&dst, temp)
+	})
+}
+
+pub fn symlink(
+	_: &Path,
+	dst: &Path,
+	target: &Path,
+	options: &Options,
+) -> Result<()> {
+	atomic::replace(dst, options, |temp| {
+		#[cfg(unix)]
+		std::os::unix::fs::symlink(target, temp)?;
+
+		#[cfg(windows)]
+		if target.is_dir() {
+			std::os::windows::fs::symlink_dir(target, temp)?;
+		} else {
+			std::os::windows::fs::symlink_file(target, temp)?;
+		}
+
+		Ok(())
+	})
 }
 
-fn remove_file_if_exists(path: &Path) -> Result<()> {
-	if path.exists() {
-		fs::remove_file(path)?;
+// there is no portable way to get the mode bits, so we
+// approximate them from the readonly flag
+pub fn mode(metadata: &fs::Metadata) -> u32 {
+	if metadata.permissions().readonly() {
+		0o444
+	} else {
+		0o644
 	}
+}
 
-	Ok(())
+pub fn preserved_differences(
+	_: &fs::Metadata,
+	_: &fs::Metadata,
+	_: &Options,
+) -> Vec<&'static str> {
+	Vec::new()
 }
@@ -1,3 +1,5 @@
+mod atomic;
+
 // Unix specific implementations
 #[cfg(all(not(feature = "no-platform-specific"), unix))]
 #[path = "unix/mod.rs"]
@@ -8,4 +10,8 @@ mod imp;
 #[path = "fallback/mod.rs"]
 mod imp;
 
-pub use imp::{copy, create_dir_all, hard_link, PlatformOptions};
+pub use atomic::resolve;
+pub use imp::{
+	copy, create_dir_all, hard_link, mode, preserved_differences,
+	symlink, write, PlatformOptions,
+};
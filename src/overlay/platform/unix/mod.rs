@@ -4,6 +4,7 @@ use std::path::Path;
 
 use anyhow::{bail, Context, Result};
 
+use super::atomic;
 use crate::cli::Options;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
@@ -27,7 +28,8 @@ pub struct PlatformOptions {
 		help = "Preserve the specified attributes",
 		value_name("attr,attr,..."),
 		value_delimiter(','),
-		conflicts_with("hard_link")
+		conflicts_with("hard_link"),
+		global = true
 	)]
 	preserve: Vec<Preserve>,
 
@@ -52,66 +54,93 @@ pub fn create_dir_all(
 	fs::set_permissions(dst_path, src_metadata.permissions())
 		.context("failed to preserve permissions")?;
 
-	for p in &options.platform_options.preserve {
-		match p {
-			Preserve::Ownership => {
-				use nix::unistd::{
-					fchownat, FchownatFlags, Gid, Uid,
-				};
+	preserve_path(&src_metadata, dst_path, options)
+}
 
-				let uid = src_metadata.uid();
-				let gid = src_metadata.gid();
+pub fn hard_link(
+	src_path: &Path,
+	dst_path: &Path,
+	options: &Options,
+) -> Result<()> {
+	atomic::replace(dst_path, options, |temp_path| {
+		fs::hard_link(src_path, temp_path)?;
+		Ok(())
+	})
+}
 
-				fchownat(
-					None,
-					dst_path,
-					Some(Uid::from_raw(uid)),
-					Some(Gid::from_raw(gid)),
-					FchownatFlags::NoFollowSymlink,
-				)
-				.context("failed to preserve ownership")?;
-			},
-			Preserve::Timestamps => {
-				use nix::sys::{
-					stat::utimensat, stat::UtimensatFlags,
-					time::TimeSpec,
-				};
+pub fn symlink(
+	src_path: &Path,
+	dst_path: &Path,
+	target: &Path,
+	options: &Options,
+) -> Result<()> {
+	let src_metadata = src_path.symlink_metadata()?;
 
-				let atime = TimeSpec::new(
-					src_metadata.atime(),
-					src_metadata.atime_nsec(),
-				);
+	// symlink(2) does not replace existing paths
+	atomic::replace(dst_path, options, |temp_path| {
+		std::os::unix::fs::symlink(target, temp_path)?;
 
-				let mtime = TimeSpec::new(
-					src_metadata.mtime(),
-					src_metadata.mtime_nsec(),
-				);
+		// symlinks have no permissions of their own
+		preserve_path(&src_metadata, temp_path, options)
+	})
+}
 
-				utimensat(
-					None,
-					dst_path,
-					&atime,
-					&mtime,
-					UtimensatFlags::NoFollowSymlink,
-				)
-				.context("failed to preserve timestamps")?;
-			},
-		}
-	}
+pub fn copy(
+	src_path: &Path,
+	dst_path: &Path,
+	options: &Options,
+) -> Result<()> {
+	let dst_path = atomic::resolve(dst_path);
 
-	Ok(())
+	atomic::replace(&dst_path, options, |temp_path| {
+		copy_new(src_path, temp_path, options)?;
+		keep_owner(&dst_path, temp_path, options)
+	})
 }
 
-pub fn hard_link(
+/// Write `contents` to `dst_path` with the attributes of
+/// `src_path`.
+pub fn write(
 	src_path: &Path,
 	dst_path: &Path,
-	_: &Options,
+	contents: &[u8],
+	options: &Options,
 ) -> Result<()> {
-	fs::hard_link(src_path, dst_path)?;
-	Ok(())
+	let src_metadata = src_path.metadata()?;
+	let dst_path = atomic::resolve(dst_path);
+
+	atomic::replace(&dst_path, options, |temp_path| {
+		fs::write(temp_path, contents)
+			.context("failed to write file data")?;
+
+		fs::set_permissions(temp_path, src_metadata.permissions())
+			.context("failed to preserve permissions")?;
+
+		preserve_path(&src_metadata, temp_path, options)?;
+		keep_owner(&dst_path, temp_path, options)
+	})
 }
 
-pub fn copy(
+/// Keep the owner of the replaced `dst_path`, unless the one
+/// of the source is preserved instead.
+fn keep_owner(
+	dst_path: &Path,
+	temp_path: &Path,
+	options: &Options,
+) -> Result<()> {
+	if options
+		.platform_options
+		.preserve
+		.contains(&Preserve::Ownership)
+	{
+		return Ok(());
+	}
+
+	atomic::keep_owner(dst_path, temp_path)
+}
+
+/// Copy `src_path` to `dst_path`, which must not exist.
+fn copy_new(
 	src_path: &Path,
 	dst_path: &Path,
 	options: &Options,
@@ -124,8 +153,7 @@ pub fn copy(
 		.context("failed to open source")?;
 	let mut dst = fs::OpenOptions::new()
 		.write(true)
-		.create(true)
-		.truncate(true)
+		.create_new(true)
 		.custom_flags(nix::libc::O_CLOEXEC)
 		.open(dst_path)
 		.context("failed to open destination")?;
@@ -214,6 +242,102 @@ pub fn copy(
 	Ok(())
 }
 
+/// Preserve attributes of `dst_path` without following symlinks
+fn preserve_path(
+	src_metadata: &fs::Metadata,
+	dst_path: &Path,
+	options: &Options,
+) -> Result<()> {
+	for p in &options.platform_options.preserve {
+		match p {
+			Preserve::Ownership => {
+				use nix::unistd::{
+					fchownat, FchownatFlags, Gid, Uid,
+				};
+
+				let uid = src_metadata.uid();
+				let gid = src_metadata.gid();
+
+				fchownat(
+					None,
+					dst_path,
+					Some(Uid::from_raw(uid)),
+					Some(Gid::from_raw(gid)),
+					FchownatFlags::NoFollowSymlink,
+				)
+				.context("failed to preserve ownership")?;
+			},
+			Preserve::Timestamps => {
+				use nix::sys::{
+					stat::utimensat, stat::UtimensatFlags,
+					time::TimeSpec,
+				};
+
+				let atime = TimeSpec::new(
+					src_metadata.atime(),
+					src_metadata.atime_nsec(),
+				);
+
+				let mtime = TimeSpec::new(
+					src_metadata.mtime(),
+					src_metadata.mtime_nsec(),
+				);
+
+				utimensat(
+					None,
+					dst_path,
+					&atime,
+					&mtime,
+					UtimensatFlags::NoFollowSymlink,
+				)
+				.context("failed to preserve timestamps")?;
+			},
+		}
+	}
+
+	Ok(())
+}
+
+/// Get the permission bits of a path
+pub fn mode(metadata: &fs::Metadata) -> u32 {
+	metadata.mode() & 0o7777
+}
+
+/// Get the preserved attributes that differ between the
+/// source and the destination
+pub fn preserved_differences(
+	src_metadata: &fs::Metadata,
+	dst_metadata: &fs::Metadata,
+	options: &Options,
+) -> Vec<&'static str> {
+	let mut differences = Vec::new();
+
+	for p in &options.platform_options.preserve {
+		match p {
+			Preserve::Ownership => {
+				if src_metadata.uid() != dst_metadata.uid()
+					|| src_metadata.gid() != dst_metadata.gid()
+				{
+					differences.push("ownership");
+				}
+			},
+			Preserve::Timestamps => {
+				// the mtime of directories changes every time their
+				// contents do and the atime changes on every read
+				if !src_metadata.is_dir()
+					&& (src_metadata.mtime() != dst_metadata.mtime()
+						|| src_metadata.mtime_nsec()
+							!= dst_metadata.mtime_nsec())
+				{
+					differences.push("timestamps");
+				}
+			},
+		}
+	}
+
+	differences
+}
+
 /// Reflink `src` to `dst`
 ///
 /// Equivalent to: `ioctl(dst, FICLONE, src)`
@@ -0,0 +1,262 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use colored::Colorize;
+use log::{info, warn};
+
+/// Keeps track of every change made to the destination so
+/// it can be undone if the install fails.
+#[derive(Debug)]
+pub struct Transaction {
+	backup_dir: PathBuf,
+	/// Paths that did not exist before, in creation order.
+	created: Vec<PathBuf>,
+	/// Files that were saved before being modified, with their
+	/// backups.
+	backups: Vec<(PathBuf, PathBuf)>,
+	/// Directories whose permissions were changed.
+	dirs: Vec<(PathBuf, fs::Permissions)>,
+	touched: HashSet<PathBuf>,
+}
+
+impl Transaction {
+	/// Start a new transaction for `dst_root`.
+	///
+	/// Backups are kept inside `dst_root` so they can be linked
+	/// and moved back with `rename(2)` which preserves all of
+	/// their attributes.
+	pub fn begin(dst_root: &Path) -> Result<Self> {
+		let backup_dir = dst_root.join(format!(
+			".turboinstall-transaction.{}",
+			std::process::id()
+		));
+
+		fs::create_dir(&backup_dir).with_context(|| {
+			format!(
+				"Unable to create transaction directory `{}`",
+				backup_dir.display()
+			)
+		})?;
+
+		Ok(Self {
+			backup_dir,
+			created: Vec::new(),
+			backups: Vec::new(),
+			dirs: Vec::new(),
+			touched: HashSet::new(),
+		})
+	}
+
+	/// The directory where backups are kept.
+	pub fn backup_dir(&self) -> &Path {
+		&self.backup_dir
+	}
+
+	/// Mark `path` as about to be created.
+	pub fn create(&mut self, path: &Path) {
+		if self.touched.insert(path.to_path_buf()) {
+			self.created.push(path.to_path_buf());
+		}
+	}
+
+	/// Save the current state of `path` before it is modified.
+	///
+	/// Files and links are hard linked into the backup
+	/// directory, so `path` stays in place until it is replaced.
+	/// Directories only have their permissions saved.
+	pub fn backup(&mut self, path: &Path) -> Result<()> {
+		if !self.touched.insert(path.to_path_buf()) {
+			return Ok(());
+		}
+
+		let metadata = path
+			.symlink_metadata()
+			.context("Failed to get metadata for backup")?;
+
+		if metadata.is_dir() {
+			self.dirs
+				.push((path.to_path_buf(), metadata.permissions()));
+			return Ok(());
+		}
+
+		let backup =
+			self.backup_dir.join(self.backups.len().to_string());
+
+		link_path(path, &backup).with_context(|| {
+			format!("Failed to back up `{}`", path.display())
+		})?;
+
+		self.backups.push((path.to_path_buf(), backup));
+
+		Ok(())
+	}
+
+	/// Remove `path` and everything inside it by moving it into
+	/// the backup directory.
+	///
+	/// Paths that did not exist before the transaction are left
+	/// for the caller to remove.
+	pub fn remove(&mut self, path: &Path) -> Result<()> {
+		let original = !self.created.iter().any(|x| x == path)
+			&& !self.backups.iter().any(|(x, _)| x == path);
+
+		if original {
+			let backup =
+				self.backup_dir.join(self.backups.len().to_string());
+
+			move_path(path, &backup).with_context(|| {
+				format!("Failed to back up `{}`", path.display())
+			})?;
+
+			self.backups.push((path.to_path_buf(), backup));
+		}
+
+		// anything installed here from now on is new
+		self.touched.retain(|x| !x.starts_with(path));
+
+		Ok(())
+	}
+
+	/// Keep all changes and discard the backups.
+	pub fn commit(self) -> Result<()> {
+		fs::remove_dir_all(&self.backup_dir).with_context(|| {
+			format!(
+				"Unable to remove transaction directory `{}`",
+				self.backup_dir.display()
+			)
+		})
+	}
+
+	/// Undo all changes.
+	///
+	/// Rolling back tries to restore as much as possible, so
+	/// errors are only reported after every change has been
+	/// attempted.
+	pub fn rollback(self) -> Result<()> {
+		let mut failed = false;
+
+		failed |= remove_paths(self.created.iter().rev());
+
+		for (path, backup) in self.backups.iter().rev() {
+			if let Err(e) = move_path(backup, path) {
+				warn!(
+					"Unable to restore `{}` from `{}`: {:#}",
+					path.display(),
+					backup.display(),
+					e
+				);
+				failed = true;
+			}
+		}
+
+		// removed directories are restored along with anything
+		// that was installed inside them before being removed
+		failed |=
+			remove_paths(self.created.iter().rev().filter(|x| {
+				self.backups.iter().all(|(y, _)| y != *x)
+					&& self
+						.backups
+						.iter()
+						.any(|(y, _)| x.starts_with(y))
+			}));
+
+		for (path, permissions) in self.dirs.iter().rev() {
+			if let Err(e) =
+				fs::set_permissions(path, permissions.clone())
+			{
+				warn!(
+					"Unable to restore permissions of `{}`: {}",
+					path.display(),
+					e
+				);
+				failed = true;
+			}
+		}
+
+		if failed {
+			warn!(
+				"Some changes could not be rolled back, backups are kept in `{}`",
+				self.backup_dir.display()
+			);
+			return Ok(());
+		}
+
+		info!(target: "no_fmt", "{:>12} all changes", "Rolled back".bold().bright_yellow());
+
+		self.commit()
+	}
+}
+
+/// Remove every path in `paths` that still exists.
+///
+/// Returns whether any of them could not be removed.
+fn remove_paths<'a>(
+	paths: impl Iterator<Item = &'a PathBuf>,
+) -> bool {
+	let mut failed = false;
+
+	for path in paths {
+		let r = match path.symlink_metadata() {
+			Ok(x) if x.is_dir() => fs::remove_dir(path),
+			Ok(_) => fs::remove_file(path),
+			Err(_) => Ok(()),
+		};
+
+		if let Err(e) = r {
+			warn!("Unable to remove `{}`: {}", path.display(), e);
+			failed = true;
+		}
+	}
+
+	failed
+}
+
+/// Hard link `from` to `to` without moving `from`, falling
+/// back to copying if that is not possible.
+fn link_path(from: &Path, to: &Path) -> Result<()> {
+	if fs::hard_link(from, to).is_ok() {
+		return Ok(());
+	}
+
+	if from.symlink_metadata()?.file_type().is_symlink() {
+		let target = fs::read_link(from)?;
+		create_symlink(&target, to)?;
+	} else {
+		fs::copy(from, to)?;
+	}
+
+	Ok(())
+}
+
+/// Move `from` to `to`, falling back to copying if they
+/// are on different filesystems.
+fn move_path(from: &Path, to: &Path) -> Result<()> {
+	if fs::rename(from, to).is_ok() {
+		return Ok(());
+	}
+
+	if from.symlink_metadata()?.file_type().is_symlink() {
+		let target = fs::read_link(from)?;
+		let _ = fs::remove_file(to);
+		create_symlink(&target, to)?;
+	} else {
+		fs::copy(from, to)?;
+	}
+
+	fs::remove_file(from)?;
+
+	Ok(())
+}
+
+#[cfg(unix)]
+fn create_symlink(target: &Path, path: &Path) -> Result<()> {
+	std::os::unix::fs::symlink(target, path)?;
+	Ok(())
+}
+
+#[cfg(not(unix))]
+fn create_symlink(_: &Path, path: &Path) -> Result<()> {
+	anyhow::bail!("Unable to move symlink `{}`", path.display())
+}
@@ -0,0 +1,102 @@
+use std::fs;
+
+use anyhow::{Context, Result};
+use colored::Colorize;
+use log::info;
+
+use super::{diff, platform, Entry};
+use crate::cli::Options;
+
+/// Check that the destination of every entry matches its source.
+///
+/// Returns whether any differences were found.
+pub fn run(entries: &[Entry], options: &Options) -> Result<bool> {
+	let mut drifted: usize = 0;
+
+	for entry in entries {
+		let differences = check(entry, options)
+			.with_context(|| format!("{}", entry.dst.display()))?;
+
+		if differences.is_empty() {
+			continue;
+		}
+
+		drifted = drifted.saturating_add(1);
+
+		if options.machine_readable {
+			for difference in &differences {
+				println!("{} {}", difference, entry.dst.display());
+			}
+		} else {
+			info!(target: "no_fmt", "{:>12} {} {}", "Differs".bold().bright_red(), entry.dst.display(), format!("({})", differences.join(", ")).dimmed());
+		}
+	}
+
+	info!(target: "no_fmt", "{:>12} {} path(s), {} differ", "Verified".bold().bright_green(), entries.len(), drifted);
+
+	Ok(drifted != 0)
+}
+
+/// Get the ways in which the destination of `entry` differs
+/// from what installing it would produce.
+fn check(
+	entry: &Entry,
+	options: &Options,
+) -> Result<Vec<&'static str>> {
+	let Entry { src, dst, target, whiteout, .. } = entry;
+
+	if *whiteout {
+		return Ok(match dst.symlink_metadata() {
+			Ok(_) => vec!["present"],
+			Err(_) => Vec::new(),
+		});
+	}
+
+	let src_metadata =
+		src.symlink_metadata().context("Failed to get metadata")?;
+
+	let dst_metadata = match target {
+		Some(_) => dst.symlink_metadata(),
+		None => dst.metadata(),
+	};
+
+	let dst_metadata = match dst_metadata {
+		Ok(v) => v,
+		Err(_) => return Ok(vec!["missing"]),
+	};
+
+	if let Some(target) = target {
+		if !dst_metadata.is_symlink() {
+			return Ok(vec!["type"]);
+		}
+
+		if &fs::read_link(dst)? != target {
+			return Ok(vec!["target"]);
+		}
+	} else if src_metadata.is_dir() != dst_metadata.is_dir() {
+		return Ok(vec!["type"]);
+	}
+
+	let mut differences = Vec::new();
+
+	if src_metadata.is_file()
+		&& !diff::same_contents(entry, &src_metadata, &dst_metadata)?
+	{
+		differences.push("content");
+	}
+
+	if target.is_none()
+		&& platform::mode(&src_metadata)
+			!= platform::mode(&dst_metadata)
+	{
+		differences.push("mode");
+	}
+
+	differences.extend(platform::preserved_differences(
+		&src_metadata,
+		&dst_metadata,
+		options,
+	));
+
+	Ok(differences)
+}
@@ -0,0 +1,593 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+
+use super::Profile;
+
+/// Replace every `{...}` in `s` with the value it expands to.
+///
+/// The supported forms are:
+/// - `{VAR}`: the value of `VAR`, which must be set and not empty
+/// - `{VAR:-default}`: `default` if `VAR` is unset or empty
+/// - `{VAR:?message}`: fail with `message` if `VAR` is unset or empty
+/// - `{VAR:+alt}`: `alt` if `VAR` is set and not empty, otherwise nothing
+///
+/// Any of them can be followed by filters that transform the
+/// value, like `{VERSION:-1.0|replace:.:_}`:
+/// - `|lower`, `|upper`
+/// - `|replace:from:to`
+/// - `|basename`, `|dirname`
+///
+/// `{{` and `}}` expand to literal braces. Expanded values are
+/// never expanded again. A `{` without a closing `}` is kept
+/// as is.
+pub fn expand_vars(s: &str, profile: &dyn Profile) -> Result<String> {
+	scan(s, |expr| expand_expr(expr, profile))
+}
+
+/// Replace every `{...}` in `s` with what `expand` returns for
+/// its contents, taking care of escaped braces.
+fn scan<'a>(
+	s: &'a str,
+	mut expand: impl FnMut(&'a str) -> Result<String>,
+) -> Result<String> {
+	let mut ret = String::with_capacity(s.len());
+	let mut rest = s;
+
+	while let Some(start) = rest.find(['{', '}']) {
+		ret.push_str(&rest[..start]);
+		rest = &rest[start..];
+
+		// escaped braces
+		if rest.starts_with("{{") || rest.starts_with("}}") {
+			ret.push_str(&rest[..1]);
+			rest = &rest[2..];
+			continue;
+		}
+
+		let end = match rest.find('}') {
+			Some(v) if rest.starts_with('{') => v,
+			// a lone `}` or a `{` that is never closed
+			_ => {
+				ret.push_str(&rest[..1]);
+				rest = &rest[1..];
+				continue;
+			},
+		};
+
+		ret.push_str(&expand(&rest[1..end])?);
+
+		rest = &rest[end.saturating_add(1)..];
+	}
+
+	ret.push_str(rest);
+
+	Ok(ret)
+}
+
+/// Replace every `{VAR...}` in the contents of a template with
+/// the value it expands to, like [`expand_vars`].
+///
+/// Only braces that start with a variable name and are closed
+/// on the same line are expanded, just like in
+/// [`expand_pattern`], so blocks like `server { ... }` are kept
+/// as is. `{{VAR}}` is kept as `{VAR}`.
+pub fn expand_template(
+	s: &str,
+	profile: &dyn Profile,
+) -> Result<String> {
+	let mut ret = String::with_capacity(s.len());
+	let mut rest = s;
+
+	while let Some(start) = rest.find('{') {
+		ret.push_str(&rest[..start]);
+		rest = &rest[start..];
+
+		let escaped = rest.starts_with("{{");
+		let inner = if escaped {
+			&rest[2..]
+		} else {
+			&rest[1..]
+		};
+
+		let end = match inner.find(['}', '\n']) {
+			Some(v)
+				if inner[v..].starts_with('}')
+					&& starts_with_name(inner) =>
+			{
+				v
+			},
+			_ => {
+				ret.push('{');
+				rest = &rest[1..];
+				continue;
+			},
+		};
+
+		if escaped {
+			ret.push('{');
+			ret.push_str(&inner[..end]);
+			ret.push('}');
+
+			// `{{VAR}` works just as well
+			rest = inner[end..]
+				.strip_prefix("}}")
+				.unwrap_or(&inner[end.saturating_add(1)..]);
+			continue;
+		}
+
+		ret.push_str(&expand_expr(&inner[..end], profile)?);
+
+		rest = &inner[end.saturating_add(1)..];
+	}
+
+	ret.push_str(rest);
+
+	Ok(ret)
+}
+
+/// Expand the contents of a single `{...}`.
+fn expand_expr(expr: &str, profile: &dyn Profile) -> Result<String> {
+	let mut parts = expr.split('|');
+
+	let mut value =
+		expand_value(parts.next().unwrap_or_default(), profile)?;
+
+	for filter in parts {
+		value = apply_filter(filter, &value).with_context(|| {
+			format!("Failed to expand `{{{}}}`", expr)
+		})?;
+	}
+
+	Ok(value)
+}
+
+/// Get the value of a variable, with an optional fallback.
+fn expand_value(expr: &str, profile: &dyn Profile) -> Result<String> {
+	if let Some((name, op)) = expr.split_once(':') {
+		let value = profile.get(name)?.filter(|x| !x.is_empty());
+
+		if let Some(default) = op.strip_prefix('-') {
+			return Ok(value.unwrap_or(default).to_string());
+		}
+
+		if let Some(message) = op.strip_prefix('?') {
+			return match value {
+				Some(v) => Ok(v.to_string()),
+				None if message.is_empty() => {
+					bail!("Variable '{}' is not set.", name)
+				},
+				None => bail!("{}: {}", name, message),
+			};
+		}
+
+		if let Some(alt) = op.strip_prefix('+') {
+			return Ok(match value {
+				Some(_) => alt.to_string(),
+				None => String::new(),
+			});
+		}
+	}
+
+	match profile.get(expr)? {
+		Some("") => bail!("Found empty variable."),
+		Some(value) => Ok(value.to_string()),
+		None => bail!("Variable '{}' not found in profile.", expr),
+	}
+}
+
+/// Transform `value` with a single `|filter`.
+fn apply_filter(filter: &str, value: &str) -> Result<String> {
+	let (name, args) = match filter.split_once(':') {
+		Some((name, args)) => (name, Some(args)),
+		None => (filter, None),
+	};
+
+	let ret = match (name, args) {
+		("lower", None) => value.to_lowercase(),
+		("upper", None) => value.to_uppercase(),
+		("replace", Some(args)) => {
+			let (from, to) =
+				match args.split_once(':') {
+					Some(("", _)) => {
+						bail!("Filter `replace` needs a non-empty pattern")
+					},
+					Some(v) => v,
+					None => bail!(
+						"Filter `replace` expects `replace:from:to`"
+					),
+				};
+
+			value.replace(from, to)
+		},
+		("basename", None) => Path::new(value)
+			.file_name()
+			.map(|x| x.to_string_lossy().into_owned())
+			.unwrap_or_default(),
+		// like dirname(1)
+		("dirname", None) => match Path::new(value).parent() {
+			Some(x) if x.as_os_str().is_empty() => ".".to_string(),
+			Some(x) => x.to_string_lossy().into_owned(),
+			None if value.starts_with('/') => "/".to_string(),
+			None => ".".to_string(),
+		},
+		_ => bail!("Unknown filter `{}`", filter),
+	};
+
+	Ok(ret)
+}
+
+/// Replace every `{VAR...}` in a path pattern with the value
+/// it expands to, passed through `escape`.
+///
+/// Only braces that start with a variable name are expanded,
+/// so regex repetitions like `{2,4}` and braces escaped with
+/// `\` are kept as is.
+pub fn expand_pattern(
+	s: &str,
+	profile: &dyn Profile,
+	escape: impl Fn(&str) -> String,
+) -> Result<String> {
+	let mut ret = String::with_capacity(s.len());
+	let mut rest = s;
+
+	while let Some(start) = rest.find('{') {
+		let escaped = rest[..start].ends_with('\\');
+		ret.push_str(&rest[..start]);
+		rest = &rest[start..];
+
+		let is_var = !escaped && starts_with_name(&rest[1..]);
+
+		let end = match rest.find('}') {
+			Some(v) if is_var => v,
+			_ => {
+				ret.push('{');
+				rest = &rest[1..];
+				continue;
+			},
+		};
+
+		ret.push_str(&escape(&expand_expr(&rest[1..end], profile)?));
+
+		rest = &rest[end.saturating_add(1)..];
+	}
+
+	ret.push_str(rest);
+
+	Ok(ret)
+}
+
+/// Whether `s` starts with something that can be the name of
+/// a variable.
+fn starts_with_name(s: &str) -> bool {
+	s.starts_with(|x: char| x.is_alphabetic() || x == '_')
+}
+
+pub fn expand_path(
+	p: impl AsRef<Path>,
+	profile: &dyn Profile,
+) -> Result<PathBuf> {
+	let mut path = PathBuf::new();
+
+	for component in p
+		.as_ref()
+		.components()
+		.map(|x| x.as_os_str().to_string_lossy())
+	{
+		let expanded = expand_vars(&component, profile)?;
+
+		// an empty component would collapse a level of the path
+		// or even turn it into its parent
+		if expanded.is_empty() {
+			bail!("`{}` expands to nothing", component)
+		}
+
+		let expanded =
+			expanded.strip_prefix('/').unwrap_or(&expanded);
+
+		path.push(expanded);
+	}
+
+	Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	use std::collections::HashMap;
+
+	#[test]
+	fn expand_vars_tests() {
+		let mut dummy_profile: HashMap<String, String> =
+			HashMap::new();
+		dummy_profile
+			.insert("var1".to_string(), "variable 1".to_string());
+		dummy_profile
+			.insert("VAR2".to_string(), "VARIABLE 2".to_string());
+		dummy_profile
+			.insert("space var".to_string(), " spaced ".to_string());
+
+		assert_eq!(
+			expand_vars("..{var1}..", &dummy_profile).unwrap(),
+			"..variable 1.."
+		);
+
+		assert_eq!(
+			expand_vars("{var1}..", &dummy_profile).unwrap(),
+			"variable 1.."
+		);
+
+		assert_eq!(
+			expand_vars("..{var1}", &dummy_profile).unwrap(),
+			"..variable 1"
+		);
+
+		assert_eq!(
+			expand_vars("{VAR2}", &dummy_profile).unwrap(),
+			"VARIABLE 2"
+		);
+
+		assert_eq!(
+			expand_vars("..{space var}..", &dummy_profile).unwrap(),
+			".. spaced .."
+		);
+
+		assert_eq!(
+			expand_vars("{var1} {VAR2}", &dummy_profile).unwrap(),
+			"variable 1 VARIABLE 2"
+		);
+
+		assert_eq!(
+			expand_vars("}var1{", &dummy_profile).unwrap(),
+			"}var1{"
+		);
+
+		assert!(expand_vars("{}{var1}{}", &dummy_profile).is_err());
+		assert!(expand_vars("{}}var1{{}", &dummy_profile).is_err());
+	}
+
+	#[test]
+	fn expand_pattern_tests() {
+		let mut dummy_profile: HashMap<String, String> =
+			HashMap::new();
+		dummy_profile
+			.insert("ARCH".to_string(), "x86.64".to_string());
+
+		let escape = |x: &str| x.replace('.', "\\.");
+
+		assert_eq!(
+			expand_pattern("^/lib/{ARCH}/", &dummy_profile, escape)
+				.unwrap(),
+			"^/lib/x86\\.64/"
+		);
+		assert_eq!(
+			expand_pattern(
+				"[0-9]{2,4}/{ARCH|upper}{3}",
+				&dummy_profile,
+				escape
+			)
+			.unwrap(),
+			"[0-9]{2,4}/X86\\.64{3}"
+		);
+		assert_eq!(
+			expand_pattern("\\{ARCH\\}", &dummy_profile, escape)
+				.unwrap(),
+			"\\{ARCH\\}"
+		);
+		assert!(
+			expand_pattern("{OS}", &dummy_profile, escape).is_err()
+		);
+	}
+
+	#[test]
+	fn expand_vars_escapes() {
+		let mut dummy_profile: HashMap<String, String> =
+			HashMap::new();
+		dummy_profile
+			.insert("var1".to_string(), "variable 1".to_string());
+
+		assert_eq!(
+			expand_vars("foo@{{}}.service", &dummy_profile).unwrap(),
+			"foo@{}.service"
+		);
+		assert_eq!(
+			expand_vars("{{var1}}", &dummy_profile).unwrap(),
+			"{var1}"
+		);
+		assert_eq!(
+			expand_vars("{{{var1}}}", &dummy_profile).unwrap(),
+			"{variable 1}"
+		);
+		assert_eq!(
+			expand_vars("a}b{c", &dummy_profile).unwrap(),
+			"a}b{c"
+		);
+	}
+
+	#[test]
+	fn expand_vars_filters() {
+		let mut dummy_profile: HashMap<String, String> =
+			HashMap::new();
+		dummy_profile.insert("NAME".to_string(), "MyApp".to_string());
+		dummy_profile
+			.insert("VERSION".to_string(), "1.2.3".to_string());
+		dummy_profile.insert(
+			"PATH".to_string(),
+			"/usr/local/bin/tool".to_string(),
+		);
+
+		assert_eq!(
+			expand_vars("{NAME|lower}", &dummy_profile).unwrap(),
+			"myapp"
+		);
+		assert_eq!(
+			expand_vars("{NAME|upper}", &dummy_profile).unwrap(),
+			"MYAPP"
+		);
+		assert_eq!(
+			expand_vars("v{VERSION|replace:.:_}", &dummy_profile)
+				.unwrap(),
+			"v1_2_3"
+		);
+		assert_eq!(
+			expand_vars("{VERSION|replace:.:}", &dummy_profile)
+				.unwrap(),
+			"123"
+		);
+		assert_eq!(
+			expand_vars("{PATH|basename}", &dummy_profile).unwrap(),
+			"tool"
+		);
+		assert_eq!(
+			expand_vars("{PATH|dirname}", &dummy_profile).unwrap(),
+			"/usr/local/bin"
+		);
+		assert_eq!(
+			expand_vars(
+				"{PATH|dirname|dirname|basename}",
+				&dummy_profile
+			)
+			.unwrap(),
+			"local"
+		);
+		assert_eq!(
+			expand_vars("{NAME|basename|dirname}", &dummy_profile)
+				.unwrap(),
+			"."
+		);
+		assert_eq!(
+			expand_vars("{UNSET:-Default|lower}", &dummy_profile)
+				.unwrap(),
+			"default"
+		);
+
+		assert!(
+			expand_vars("{NAME|unknown}", &dummy_profile).is_err()
+		);
+		assert!(
+			expand_vars("{NAME|replace:x}", &dummy_profile).is_err()
+		);
+		assert!(
+			expand_vars("{NAME|replace::x}", &dummy_profile).is_err()
+		);
+		assert!(expand_vars("{UNSET|lower}", &dummy_profile).is_err());
+	}
+
+	#[test]
+	fn expand_vars_fallbacks() {
+		let mut dummy_profile: HashMap<String, String> =
+			HashMap::new();
+		dummy_profile
+			.insert("var1".to_string(), "variable 1".to_string());
+		dummy_profile.insert("empty".to_string(), "".to_string());
+
+		assert_eq!(
+			expand_vars("{var1:-default}", &dummy_profile).unwrap(),
+			"variable 1"
+		);
+		assert_eq!(
+			expand_vars("{unset:-default}", &dummy_profile).unwrap(),
+			"default"
+		);
+		assert_eq!(
+			expand_vars("{empty:-default}", &dummy_profile).unwrap(),
+			"default"
+		);
+		assert_eq!(
+			expand_vars("a{unset:-}b", &dummy_profile).unwrap(),
+			"ab"
+		);
+
+		assert_eq!(
+			expand_vars("{var1:+alt}", &dummy_profile).unwrap(),
+			"alt"
+		);
+		assert_eq!(
+			expand_vars("{unset:+alt}", &dummy_profile).unwrap(),
+			""
+		);
+		assert_eq!(
+			expand_vars("{empty:+alt}", &dummy_profile).unwrap(),
+			""
+		);
+
+		assert_eq!(
+			expand_vars("{var1:?must be set}", &dummy_profile)
+				.unwrap(),
+			"variable 1"
+		);
+
+		let e = expand_vars("{unset:?set it}", &dummy_profile)
+			.unwrap_err();
+		assert_eq!(e.to_string(), "unset: set it");
+
+		assert!(
+			expand_vars("{empty:?set it}", &dummy_profile).is_err()
+		);
+		assert!(expand_vars("{unset:?}", &dummy_profile).is_err());
+
+		// anything else is part of the name
+		dummy_profile.insert("a:b".to_string(), "colon".to_string());
+		assert_eq!(
+			expand_vars("{a:b}", &dummy_profile).unwrap(),
+			"colon"
+		);
+	}
+
+	#[test]
+	fn expand_path_empty_components() {
+		let mut dummy_profile: HashMap<String, String> =
+			HashMap::new();
+		dummy_profile.insert("DIR".to_string(), "/usr".to_string());
+
+		assert_eq!(
+			expand_path("{DIR}/{UNSET:-bin}", &dummy_profile)
+				.unwrap(),
+			Path::new("usr/bin")
+		);
+		assert_eq!(
+			expand_path("sub/{UNSET:+x}.conf", &dummy_profile)
+				.unwrap(),
+			Path::new("sub/.conf")
+		);
+		assert!(expand_path("sub/{UNSET:-}", &dummy_profile).is_err());
+		assert!(
+			expand_path("{UNSET:+x}/file", &dummy_profile).is_err()
+		);
+	}
+
+	#[test]
+	fn expand_template_tests() {
+		let mut dummy_profile: HashMap<String, String> =
+			HashMap::new();
+		dummy_profile.insert("DIR".to_string(), "/usr".to_string());
+
+		assert_eq!(
+			expand_template(
+				"server {\n\troot {DIR};\n}\n",
+				&dummy_profile
+			)
+			.unwrap(),
+			"server {\n\troot /usr;\n}\n"
+		);
+		assert_eq!(
+			expand_template(
+				"{\"a\": {\"b\": \"{DIR:-x}\"}}",
+				&dummy_profile
+			)
+			.unwrap(),
+			"{\"a\": {\"b\": \"/usr\"}}"
+		);
+		assert_eq!(
+			expand_template("{{DIR}} {{DIR} {{ x }}", &dummy_profile)
+				.unwrap(),
+			"{DIR} {DIR} {{ x }}"
+		);
+		assert_eq!(
+			expand_template("{DIR\n}", &dummy_profile).unwrap(),
+			"{DIR\n}"
+		);
+		assert!(expand_template("{MISSING}", &dummy_profile).is_err());
+	}
+}
@@ -1,8 +1,12 @@
-use std::path::Path;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock, PoisonError};
+use std::thread::{self, ThreadId};
 
 use anyhow::{bail, Context, Result};
 
 mod env;
+pub mod expand;
 mod serde;
 
 #[derive(Debug, Clone, Copy, clap::ValueEnum)]
@@ -16,26 +20,207 @@ pub enum Format {
 pub trait Profile {
 	fn var(&self, item: &str) -> Option<&str>;
 	fn list(&self) -> Vec<(String, String)>;
+
+	/// Get the value of `item`, failing if it is set but its
+	/// value can not be determined.
+	fn get(&self, item: &str) -> Result<Option<&str>> {
+		Ok(self.var(item))
+	}
+}
+
+impl Profile for HashMap<String, String> {
+	fn var(&self, item: &str) -> Option<&str> {
+		self.get(item).map(|x| x.as_str())
+	}
+
+	fn list(&self) -> Vec<(String, String)> {
+		self.iter().map(|(k, v)| (k.clone(), v.clone())).collect()
+	}
+}
+
+/// Get the variables of the environment starting with `prefix`,
+/// with the prefix removed.
+pub fn from_env(prefix: &str) -> HashMap<String, String> {
+	std::env::vars()
+		.filter_map(|(k, v)| {
+			k.strip_prefix(prefix)
+				.filter(|x| !x.is_empty())
+				.map(|x| (x.to_string(), v))
+		})
+		.collect()
+}
+
+/// A profile whose values can refer to other variables, like
+/// `BIN = "{PREFIX}/bin"`.
+///
+/// References are resolved only when a variable is used, so
+/// values that are never expanded can contain anything. This
+/// can happen from multiple threads at once.
+#[derive(Debug, Default)]
+pub struct Resolved {
+	vars: HashMap<String, Value>,
+	/// The variables being resolved by each thread, to find
+	/// cycles
+	stack: Mutex<Vec<(ThreadId, String)>>,
 }
 
-pub struct NoProfile;
+#[derive(Debug)]
+struct Value {
+	raw: String,
+	/// Whether `raw` is used as is
+	literal: bool,
+	resolved: OnceLock<String>,
+}
+
+impl Resolved {
+	/// Add variables whose values can refer to other variables,
+	/// overriding existing ones.
+	pub fn extend(
+		&mut self,
+		vars: impl IntoIterator<Item = (String, String)>,
+	) {
+		for (k, v) in vars {
+			self.insert(k, v, false);
+		}
+
+		self.forget();
+	}
+
+	/// Add variables whose values are used as they are,
+	/// overriding existing ones.
+	pub fn extend_literal(
+		&mut self,
+		vars: impl IntoIterator<Item = (String, String)>,
+	) {
+		for (k, v) in vars {
+			self.insert(k, v, true);
+		}
+
+		self.forget();
+	}
+
+	/// Add all variables of `other`, overriding existing ones.
+	pub fn extend_from(&mut self, other: &Self) {
+		for (k, v) in &other.vars {
+			self.insert(k.clone(), v.raw.clone(), v.literal);
+		}
+
+		self.forget();
+	}
+
+	fn insert(&mut self, name: String, raw: String, literal: bool) {
+		self.vars.insert(
+			name,
+			Value { raw, literal, resolved: OnceLock::new() },
+		);
+	}
 
-impl NoProfile {
-	pub fn new() -> Self {
-		Self
+	/// Drop every resolved value, as they may depend on the
+	/// variables that were just added.
+	fn forget(&mut self) {
+		for value in self.vars.values_mut() {
+			value.resolved.take();
+		}
 	}
 }
 
-impl Profile for NoProfile {
-	fn var(&self, _: &str) -> Option<&str> {
-		None
+impl Profile for Resolved {
+	fn var(&self, item: &str) -> Option<&str> {
+		self.get(item).ok().flatten()
 	}
 
+	/// Variables whose references can not be resolved are
+	/// listed with their raw value.
 	fn list(&self) -> Vec<(String, String)> {
-		Vec::new()
+		self.vars
+			.iter()
+			.map(|(k, v)| {
+				let value = self.var(k).unwrap_or(&v.raw);
+				(k.clone(), value.to_string())
+			})
+			.collect()
+	}
+
+	fn get(&self, item: &str) -> Result<Option<&str>> {
+		let value = match self.vars.get(item) {
+			Some(v) => v,
+			None => return Ok(None),
+		};
+
+		if value.literal {
+			return Ok(Some(&value.raw));
+		}
+
+		if let Some(v) = value.resolved.get() {
+			return Ok(Some(v));
+		}
+
+		let thread = thread::current().id();
+
+		{
+			let mut stack = self
+				.stack
+				.lock()
+				.unwrap_or_else(PoisonError::into_inner);
+
+			let resolving: Vec<&str> = stack
+				.iter()
+				.filter(|(x, _)| *x == thread)
+				.map(|(_, x)| x.as_str())
+				.collect();
+
+			if let Some(i) = resolving.iter().position(|x| *x == item)
+			{
+				let mut cycle = resolving[i..].to_vec();
+				cycle.push(item);
+
+				bail!(
+					"Variable '{}' references itself: {}",
+					item,
+					cycle.join(" -> ")
+				)
+			}
+
+			stack.push((thread, item.to_string()));
+		}
+
+		let resolved = expand::expand_vars(&value.raw, self)
+			.with_context(|| {
+				format!("Failed to resolve variable '{}'", item)
+			});
+
+		{
+			let mut stack = self
+				.stack
+				.lock()
+				.unwrap_or_else(PoisonError::into_inner);
+
+			if let Some(i) =
+				stack.iter().rposition(|(x, _)| *x == thread)
+			{
+				stack.remove(i);
+			}
+		}
+
+		let resolved = resolved?;
+		Ok(Some(value.resolved.get_or_init(|| resolved)))
+	}
+}
+
+/// Parse a `KEY=VALUE` definition.
+pub fn parse_define(s: &str) -> Result<(String, String)> {
+	match s.split_once('=') {
+		Some(("", _)) => bail!("Missing variable name in `{}`", s),
+		Some((k, v)) => Ok((k.to_string(), v.to_string())),
+		None => bail!("Missing assignment operator in `{}`", s),
 	}
 }
 
+/// Keys whose value is the path, or a list of paths, of other
+/// profiles to load before this one, relative to the profile
+/// containing them.
+static INCLUDE_KEYS: &[&str] = &["extends", "include"];
+
 pub fn load_file(
 	file: &Path,
 	mut fmt: Option<Format>,
@@ -45,21 +230,96 @@ pub fn load_file(
 		file.display()
 	))?;
 
-	if fmt.is_none() {
-		if let Some(ext) = file.extension() {
-			fmt = Some(match ext.to_string_lossy().as_ref() {
-				"json" => Format::Json,
-				"toml" => Format::Toml,
-				"yaml" | "yml" => Format::Yaml,
-				"env" => Format::Env,
-				_ => bail!("Unable to determine profile format. Please use `--format`!")
-			})
-		}
+	if fmt.is_none() && file.extension().is_some() {
+		fmt = Some(match detect_format(file) {
+			Some(v) => v,
+			None => bail!("Unable to determine profile format. Please use `--format`!")
+		})
 	}
 
 	load_str(&raw, fmt)
 }
 
+/// Load `file` along with all profiles it includes.
+///
+/// The variables of `file` override those of the included
+/// profiles.
+pub fn load_layered(
+	file: &Path,
+	fmt: Option<Format>,
+) -> Result<HashMap<String, String>> {
+	load_layered_inner(file, fmt, &mut Vec::new())
+}
+
+fn load_layered_inner(
+	file: &Path,
+	fmt: Option<Format>,
+	stack: &mut Vec<PathBuf>,
+) -> Result<HashMap<String, String>> {
+	let canonical = file.canonicalize().with_context(|| {
+		format!("Unable to read profile '{}'", file.display())
+	})?;
+
+	if stack.contains(&canonical) {
+		bail!("Profile '{}' includes itself", file.display())
+	}
+
+	stack.push(canonical);
+
+	let mut own: HashMap<String, String> =
+		load_file(file, fmt)?.list().into_iter().collect();
+
+	let mut vars = HashMap::new();
+
+	for key in INCLUDE_KEYS {
+		let includes = match own.remove(*key) {
+			Some(v) => v,
+			None => continue,
+		};
+
+		// the items of lists are also available as `key.N`
+		let prefix = format!("{}.", key);
+		own.retain(|k, _| !k.starts_with(&prefix));
+
+		for include in includes.lines() {
+			let include = file
+				.parent()
+				.unwrap_or_else(|| Path::new(""))
+				.join(include);
+
+			// included profiles use the format of their
+			// extension, or the one of the including profile
+			let include_fmt = detect_format(&include).or(fmt);
+
+			vars.extend(
+				load_layered_inner(&include, include_fmt, stack)
+					.with_context(|| {
+						format!(
+							"Unable to include profile '{}'",
+							include.display()
+						)
+					})?,
+			);
+		}
+	}
+
+	vars.extend(own);
+
+	stack.pop();
+
+	Ok(vars)
+}
+
+fn detect_format(file: &Path) -> Option<Format> {
+	match file.extension()?.to_string_lossy().as_ref() {
+		"json" => Some(Format::Json),
+		"toml" => Some(Format::Toml),
+		"yaml" | "yml" => Some(Format::Yaml),
+		"env" => Some(Format::Env),
+		_ => None,
+	}
+}
+
 pub fn load_str(
 	raw: &str,
 	fmt: Option<Format>,
@@ -78,3 +338,58 @@ pub fn load_str(
 		Format::Env => env::EnvProfile::from_str(raw),
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn vars(pairs: &[(&str, &str)]) -> Resolved {
+		let mut ret = Resolved::default();
+		ret.extend(
+			pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())),
+		);
+		ret
+	}
+
+	#[test]
+	fn resolve_references() {
+		let resolved = vars(&[
+			("PREFIX", "/usr"),
+			("BIN", "{PREFIX}/bin"),
+			("TOOL", "{BIN}/{NAME:-tool|upper}"),
+			("LITERAL", "{{PREFIX}}"),
+		]);
+
+		assert_eq!(resolved.var("BIN"), Some("/usr/bin"));
+		assert_eq!(resolved.var("TOOL"), Some("/usr/bin/TOOL"));
+		assert_eq!(resolved.var("LITERAL"), Some("{PREFIX}"));
+
+		let mut resolved = vars(&[("BIN", "{PREFIX}/bin")]);
+		resolved.extend_literal([
+			("PREFIX".to_string(), "/opt".to_string()),
+			("FORMAT".to_string(), "{time}".to_string()),
+		]);
+
+		assert_eq!(resolved.var("BIN"), Some("/opt/bin"));
+		assert_eq!(resolved.var("FORMAT"), Some("{time}"));
+	}
+
+	#[test]
+	fn resolve_cycles() {
+		let resolved = vars(&[
+			("A", "{B}"),
+			("B", "{C}"),
+			("C", "{A}"),
+			("D", "value"),
+			("E", "{MISSING}"),
+		]);
+
+		let e = resolved.get("A").unwrap_err();
+		assert!(format!("{:#}", e).contains("A -> B -> C -> A"));
+
+		// only the variables that are used are resolved
+		assert_eq!(resolved.get("D").unwrap(), Some("value"));
+		assert!(resolved.get("E").is_err());
+		assert_eq!(resolved.var("E"), None);
+	}
+}
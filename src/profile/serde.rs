@@ -1,35 +1,126 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
 use anyhow::{Context, Result};
 
 use super::Profile;
 
+/// Separator between the keys of nested tables.
+static KEY_SEPARATOR: &str = ".";
+
+/// Separator between the items of lists.
+static LIST_SEPARATOR: &str = "\n";
+
+// the key toml uses to deserialize its datetimes
+static TOML_DATETIME_KEY: &str = "$__toml_private_datetime";
+
 #[derive(Debug, serde::Deserialize)]
+#[serde(untagged)]
+enum Value {
+	Bool(bool),
+	Int(i64),
+	UInt(u64),
+	Float(f64),
+	String(String),
+	List(Vec<Value>),
+	Table(BTreeMap<String, Value>),
+	Null(()),
+}
+
+impl Value {
+	/// Get the value as a string, if it is a single value.
+	fn scalar(&self) -> Option<String> {
+		match self {
+			Self::Bool(x) => Some(x.to_string()),
+			Self::Int(x) => Some(x.to_string()),
+			Self::UInt(x) => Some(x.to_string()),
+			Self::Float(x) => Some(x.to_string()),
+			Self::String(x) => Some(x.clone()),
+			Self::Table(x) if x.len() == 1 => {
+				x.get(TOML_DATETIME_KEY).and_then(|x| x.scalar())
+			},
+			_ => None,
+		}
+	}
+
+	/// Add the value as `key` to `vars`, along with every
+	/// value nested inside it.
+	///
+	/// Nested tables are flattened to `key.nested` and the
+	/// items of lists are available as `key.0`, `key.1` and
+	/// so on. Lists of single values are also available as
+	/// `key`, with one item per line.
+	fn flatten(&self, key: &str, vars: &mut HashMap<String, String>) {
+		if let Some(x) = self.scalar() {
+			vars.insert(key.to_string(), x);
+			return;
+		}
+
+		match self {
+			Self::List(items) => {
+				let scalars: Option<Vec<String>> =
+					items.iter().map(|x| x.scalar()).collect();
+
+				if let Some(scalars) = scalars {
+					vars.insert(
+						key.to_string(),
+						scalars.join(LIST_SEPARATOR),
+					);
+				}
+
+				for (i, item) in items.iter().enumerate() {
+					item.flatten(
+						&format!("{}{}{}", key, KEY_SEPARATOR, i),
+						vars,
+					);
+				}
+			},
+			Self::Table(table) => {
+				for (k, v) in table {
+					v.flatten(
+						&format!("{}{}{}", key, KEY_SEPARATOR, k),
+						vars,
+					);
+				}
+			},
+			_ => {},
+		}
+	}
+}
+
+#[derive(Debug)]
 pub struct SerdeProfile {
-	#[serde(flatten)]
 	inner: HashMap<String, String>,
 }
 
 impl SerdeProfile {
 	pub fn from_json(s: &str) -> Result<Box<dyn Profile>> {
-		Ok(Box::new(
-			serde_json::from_str::<Self>(s)
+		Ok(Box::new(Self::from_table(
+			serde_json::from_str(s)
 				.context("Unable to parse json")?,
-		))
+		)))
 	}
 
 	pub fn from_toml(s: &str) -> Result<Box<dyn Profile>> {
-		Ok(Box::new(
-			toml::from_str::<Self>(s)
-				.context("Unable to parse toml")?,
-		))
+		Ok(Box::new(Self::from_table(
+			toml::from_str(s).context("Unable to parse toml")?,
+		)))
 	}
 
 	pub fn from_yaml(s: &str) -> Result<Box<dyn Profile>> {
-		Ok(Box::new(
-			serde_yaml::from_str::<Self>(s)
+		Ok(Box::new(Self::from_table(
+			serde_yaml::from_str(s)
 				.context("Unable to parse yaml")?,
-		))
+		)))
+	}
+
+	fn from_table(table: BTreeMap<String, Value>) -> Self {
+		let mut inner = HashMap::new();
+
+		for (k, v) in &table {
+			v.flatten(k, &mut inner);
+		}
+
+		Self { inner }
 	}
 }
 
@@ -45,3 +136,59 @@ impl Profile for SerdeProfile {
 			.collect()
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn nested_values() {
+		let profile = SerdeProfile::from_toml(
+			r#"
+			DIR = "/usr"
+			JOBS = 4
+			RATIO = 0.5
+			DEBUG = false
+			LIBS = ["a", "b"]
+			DATE = 1979-05-27
+
+			[paths]
+			bin = "/usr/bin"
+
+			[[servers]]
+			name = "one"
+			"#,
+		)
+		.unwrap();
+
+		assert_eq!(profile.var("DIR"), Some("/usr"));
+		assert_eq!(profile.var("JOBS"), Some("4"));
+		assert_eq!(profile.var("RATIO"), Some("0.5"));
+		assert_eq!(profile.var("DEBUG"), Some("false"));
+		assert_eq!(profile.var("LIBS"), Some("a\nb"));
+		assert_eq!(profile.var("LIBS.1"), Some("b"));
+		assert_eq!(profile.var("DATE"), Some("1979-05-27"));
+		assert_eq!(profile.var("paths.bin"), Some("/usr/bin"));
+		assert_eq!(profile.var("servers.0.name"), Some("one"));
+		assert_eq!(profile.var("paths"), None);
+		assert_eq!(profile.var("servers"), None);
+	}
+
+	#[test]
+	fn null_values() {
+		let profile =
+			SerdeProfile::from_yaml("DIR: ~\nLIST: [1, null]\n")
+				.unwrap();
+
+		assert_eq!(profile.var("DIR"), None);
+		assert_eq!(profile.var("LIST"), None);
+		assert_eq!(profile.var("LIST.0"), Some("1"));
+
+		let profile =
+			SerdeProfile::from_json(r#"{ "a": { "b": [true] } }"#)
+				.unwrap();
+
+		assert_eq!(profile.var("a.b"), Some("true"));
+		assert_eq!(profile.var("a.b.0"), Some("true"));
+	}
+}
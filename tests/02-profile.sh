@@ -7,16 +7,26 @@ function dir_tree_contents {
 
 src="$TEST_DIR/profile-tree"
 
+# $1 - path to the new source tree
+function copy_without_profile {
+	cp -r "$src" "$1"
+	rm "$1"/.turboinstall/profile.*
+}
+
 # $1 - profile type
 function run_profile_test {
 	local ptype="${1:?}"
 
 	local dst="$PWD/profile-tree-${ptype}"
+	local tree="$PWD/profile-tree-${ptype}-src"
 	mkdir "$dst"
 
+	# only the given profile can provide the variables
+	copy_without_profile "$tree"
+
 	turboinstall \
 		-p "$src/.turboinstall/profile.${ptype}" \
-		-- "$dst" "$src"
+		-- "$dst" "$tree"
 
 	mapfile -t dst_paths < <(dir_tree_contents "$dst")
 
@@ -39,3 +49,27 @@ function test_yaml_profile {
 function test_env_profile {
 	run_profile_test "env"
 }
+
+function test_overlay_profile {
+	local dst="$PWD/profile-tree-overlay"
+	mkdir "$dst"
+
+	turboinstall -- "$dst" "$src"
+
+	mapfile -t dst_paths < <(dir_tree_contents "$dst")
+
+	assert_eq "${dst_paths[*]}" \
+		"/ /usr /usr/local /usr/local/file_VALUE_1 /VALUE_1"
+}
+
+function test_overlay_profile_override {
+	local dst="$PWD/profile-tree-overlay-override"
+	mkdir "$dst"
+
+	turboinstall -D DIR=/opt -- "$dst" "$src"
+
+	mapfile -t dst_paths < <(dir_tree_contents "$dst")
+
+	assert_eq "${dst_paths[*]}" \
+		"/ /opt /opt/file_VALUE_1 /VALUE_1"
+}
@@ -0,0 +1,40 @@
+#!/bin/bash
+
+# $1 - path to dir
+function dir_tree_contents {
+	(cd -- "$1" && find . -printf '/%P\n' | sort -df)
+}
+
+src="$TEST_DIR/simple-tree"
+
+function test_uninstall {
+	local dst="$PWD/manifest-tree"
+	local manifest="$PWD/manifest-tree.json"
+
+	mkdir "$dst"
+	touch "$dst/existing"
+
+	turboinstall --manifest "$manifest" -- "$dst" "$src"
+	turboinstall uninstall "$manifest"
+
+	mapfile -t dst_paths < <(dir_tree_contents "$dst")
+
+	assert_eq "${dst_paths[*]}" \
+		"/ /existing"
+}
+
+function test_uninstall_keeps_modified {
+	local dst="$PWD/manifest-tree-modified"
+	local manifest="$PWD/manifest-tree-modified.json"
+
+	mkdir "$dst"
+
+	turboinstall --manifest "$manifest" -- "$dst" "$src"
+	echo "modified" > "$dst/dir1/file1"
+	turboinstall uninstall "$manifest"
+
+	mapfile -t dst_paths < <(dir_tree_contents "$dst")
+
+	assert_eq "${dst_paths[*]}" \
+		"/ /dir1 /dir1/file1"
+}
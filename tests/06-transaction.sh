@@ -0,0 +1,64 @@
+#!/bin/bash
+
+# $1 - path to dir
+function dir_tree_contents {
+	(cd -- "$1" && find . -printf '/%P\n' | sort -df)
+}
+
+# $1 - path to the new source tree
+function make_failing_tree {
+	cp -r "$TEST_DIR/simple-tree" "$1"
+
+	mkdir -p "$1/.turboinstall/post-install"
+	printf '#!/bin/sh\nexit 1\n' > "$1/.turboinstall/post-install/fail.sh"
+	chmod +x "$1/.turboinstall/post-install/fail.sh"
+}
+
+function test_rollback {
+	local dst="$PWD/transaction-tree"
+	local src="$PWD/transaction-tree-src"
+
+	make_failing_tree "$src"
+
+	mkdir -p "$dst/dir1"
+	echo "original" > "$dst/file0"
+
+	! turboinstall --transaction -- "$dst" "$src"
+
+	mapfile -t dst_paths < <(dir_tree_contents "$dst")
+
+	assert_eq "${dst_paths[*]}" \
+		"/ /dir1 /file0"
+	assert_eq "$(cat "$dst/file0")" "original"
+}
+
+function test_commit {
+	local dst="$PWD/transaction-tree-commit"
+	local src="$TEST_DIR/simple-tree"
+
+	mkdir "$dst"
+
+	turboinstall --transaction -- "$dst" "$src"
+
+	mapfile -t dst_paths < <(dir_tree_contents "$dst")
+
+	assert_eq "${dst_paths[*]}" \
+		"/ /dir1 /dir1/dir2 /dir1/dir2/file2 /dir1/file1 /file0"
+}
+
+function test_rollback_symlink {
+	local dst="$PWD/transaction-tree-symlink"
+	local src="$PWD/transaction-tree-symlink-src"
+	local outside="$PWD/transaction-tree-symlink-outside"
+
+	make_failing_tree "$src"
+
+	mkdir -p "$dst"
+	echo "original" > "$outside"
+	ln -s "$outside" "$dst/file0"
+
+	! turboinstall --transaction -- "$dst" "$src"
+
+	assert [ -L "$dst/file0" ]
+	assert_eq "$(cat "$outside")" "original"
+}
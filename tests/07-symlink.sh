@@ -0,0 +1,40 @@
+#!/bin/bash
+
+src="$TEST_DIR/symlink-tree"
+
+function test_symlinks {
+	local dst="$PWD/symlink-tree"
+
+	mkdir "$dst"
+
+	turboinstall -- "$dst" "$src"
+
+	assert [ -L "$dst/link0" ]
+	assert [ -L "$dst/dangling" ]
+	assert_eq "$(readlink "$dst/link0")" "file0"
+	assert_eq "$(readlink "$dst/absolute")" "/{DIR}/file"
+}
+
+function test_expand_link_targets {
+	local dst="$PWD/symlink-tree-expand"
+
+	mkdir "$dst"
+
+	turboinstall \
+		--expand-link-targets \
+		-p "$TEST_DIR/profile-tree/.turboinstall/profile.json" \
+		-- "$dst" "$src"
+
+	assert_eq "$(readlink "$dst/absolute")" "/usr/local/file"
+}
+
+function test_dereference {
+	local dst="$PWD/symlink-tree-dereference"
+
+	mkdir "$dst"
+
+	turboinstall --dereference --ignore '^/(dangling|absolute)$' -- "$dst" "$src"
+
+	assert [ ! -L "$dst/link0" ]
+	assert [ -f "$dst/link0" ]
+}
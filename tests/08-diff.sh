@@ -0,0 +1,41 @@
+#!/bin/bash
+
+src="$TEST_DIR/simple-tree"
+
+function test_diff_pending {
+	local dst="$PWD/diff-tree"
+
+	mkdir "$dst"
+
+	local code=0
+	turboinstall diff --porcelain -- "$dst" "$src" > "$dst.out" || code=$?
+
+	assert_eq "$code" 2
+	assert_eq "$(cut -d' ' -f1 "$dst.out" | sort -u)" "create"
+}
+
+function test_diff_unchanged {
+	local dst="$PWD/diff-tree-unchanged"
+
+	mkdir "$dst"
+
+	turboinstall -- "$dst" "$src"
+	turboinstall diff --porcelain -- "$dst" "$src" > "$dst.out"
+
+	assert_eq "$(cut -d' ' -f1 "$dst.out" | sort -u)" "unchanged"
+}
+
+function test_diff_overwrite {
+	local dst="$PWD/diff-tree-overwrite"
+
+	mkdir "$dst"
+
+	turboinstall -- "$dst" "$src"
+	echo "modified" > "$dst/file0"
+
+	local code=0
+	turboinstall diff --porcelain -- "$dst" "$src" > "$dst.out" || code=$?
+
+	assert_eq "$code" 2
+	assert_eq "$(grep -c '^overwrite ' "$dst.out")" 1
+}
@@ -0,0 +1,30 @@
+#!/bin/bash
+
+src="$TEST_DIR/simple-tree"
+
+function test_verify_clean {
+	local dst="$PWD/verify-tree"
+
+	mkdir "$dst"
+
+	turboinstall -- "$dst" "$src"
+	turboinstall verify -- "$dst" "$src"
+}
+
+function test_verify_drift {
+	local dst="$PWD/verify-tree-drift"
+
+	mkdir "$dst"
+
+	turboinstall -- "$dst" "$src"
+	echo "modified" > "$dst/file0"
+	chmod 600 "$dst/dir1/file1"
+	rm "$dst/dir1/dir2/file2"
+
+	local code=0
+	turboinstall verify --porcelain -- "$dst" "$src" > "$dst.out" || code=$?
+
+	assert_eq "$code" 2
+	assert_eq "$(cut -d' ' -f1 "$dst.out" | sort | xargs)" \
+		"content missing mode"
+}
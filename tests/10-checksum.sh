@@ -0,0 +1,18 @@
+#!/bin/bash
+
+src="$TEST_DIR/simple-tree"
+
+function test_checksum_skips_identical {
+	local dst="$PWD/checksum-tree"
+
+	mkdir "$dst"
+
+	turboinstall -- "$dst" "$src"
+	touch -d "2000-01-01" "$dst/file0" "$dst/dir1/file1"
+	echo "modified" > "$dst/dir1/file1"
+
+	turboinstall --checksum -- "$dst" "$src"
+
+	assert_eq "$(stat -c %Y "$dst/file0")" "$(date -d 2000-01-01 +%s)"
+	assert [ ! -s "$dst/dir1/file1" ]
+}
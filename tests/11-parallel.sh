@@ -0,0 +1,44 @@
+#!/bin/bash
+
+# $1 - path to dir
+function dir_tree_hash {
+	(cd -- "$1" && find .) | sort -df | sha1sum | cut -d' ' -f1
+}
+
+src="$TEST_DIR/simple-tree"
+
+function test_parallel_install {
+	local dst="$PWD/parallel-tree"
+	local dst_serial="$PWD/parallel-tree-serial"
+
+	mkdir "$dst" "$dst_serial"
+
+	turboinstall --porcelain -- "$dst_serial" "$src" > "$dst_serial.out"
+	turboinstall --porcelain --jobs 4 -- "$dst" "$src" > "$dst.out"
+
+	assert_eq "$(dir_tree_hash "$src")" "$(dir_tree_hash "$dst")"
+	assert_eq \
+		"$(sed "s|$dst_serial|DST|" "$dst_serial.out")" \
+		"$(sed "s|$dst|DST|" "$dst.out")"
+}
+
+function test_parallel_zero_jobs {
+	local dst="$PWD/parallel-tree-zero"
+
+	mkdir "$dst"
+
+	! turboinstall --jobs 0 -- "$dst" "$src"
+}
+
+function test_parallel_abort {
+	local dst="$PWD/parallel-tree-abort"
+	local src="$PWD/parallel-tree-abort-src"
+
+	mkdir -p "$dst" "$src/a" "$src/b"
+	touch "$src/a/f" "$src/b/g"
+	ln -s "$PWD/parallel-tree-abort-missing" "$dst/a"
+
+	! turboinstall --jobs 4 -- "$dst" "$src"
+
+	assert [ ! -e "$dst/b" ]
+}
@@ -0,0 +1,68 @@
+#!/bin/bash
+
+src="$TEST_DIR/whiteout-tree"
+
+# $1 - path to dir
+function dir_tree_contents {
+	(cd -- "$1" && find . -printf '/%P\n' | sort -df)
+}
+
+function test_whiteout {
+	local dst="$PWD/whiteout-tree"
+
+	mkdir "$dst"
+
+	turboinstall -- "$dst" "$TEST_DIR/simple-tree" "$src"
+
+	mapfile -t dst_paths < <(dir_tree_contents "$dst")
+
+	assert_eq "${dst_paths[*]}" \
+		"/ /dir1 /dir1/file1"
+}
+
+function test_whiteout_transaction {
+	local dst="$PWD/whiteout-tree-transaction"
+	local failing="$PWD/whiteout-tree-failing"
+
+	mkdir -p "$failing/.turboinstall/post-install"
+	printf '#!/bin/sh\nexit 1\n' > "$failing/.turboinstall/post-install/fail.sh"
+	chmod +x "$failing/.turboinstall/post-install/fail.sh"
+
+	mkdir "$dst"
+	turboinstall -- "$dst" "$TEST_DIR/simple-tree"
+
+	! turboinstall --transaction -- "$dst" "$src" "$failing"
+
+	mapfile -t dst_paths < <(dir_tree_contents "$dst")
+
+	assert_eq "${dst_paths[*]}" \
+		"/ /dir1 /dir1/dir2 /dir1/dir2/file2 /dir1/file1 /file0"
+}
+
+function test_whiteout_diff {
+	local dst="$PWD/whiteout-tree-diff"
+
+	mkdir "$dst"
+	turboinstall -- "$dst" "$TEST_DIR/simple-tree"
+
+	turboinstall diff --porcelain -- "$dst" "$src" > "$dst.out" || true
+	assert_eq "$(grep -c '^remove' "$dst.out")" "2"
+
+	turboinstall diff --porcelain -- "$dst" "$TEST_DIR/simple-tree" "$src" > "$dst.out" || true
+	assert_eq "$(grep -c 'file2' "$dst.out")" "0"
+}
+
+function test_whiteout_root {
+	local dst="$PWD/whiteout-tree-root"
+	local parent="$PWD/whiteout-tree-root-parent"
+	local empty="$PWD/whiteout-tree-root-empty"
+
+	mkdir -p "$dst" "$parent/sub" "$empty"
+	echo "keep" > "$dst/file0"
+	touch "$parent/sub/.wh.." "$empty/.wh.{X:+foo}"
+
+	! turboinstall -- "$dst" "$parent"
+	! turboinstall -- "$dst" "$empty"
+
+	assert_eq "$(cat "$dst/file0")" "keep"
+}
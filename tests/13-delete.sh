@@ -0,0 +1,100 @@
+#!/bin/bash
+
+src="$TEST_DIR/simple-tree"
+
+# $1 - path to dir
+function dir_tree_contents {
+	(cd -- "$1" && find . -printf '/%P\n' | sort -df)
+}
+
+# $1 - path to the destination
+function add_stale_files {
+	mkdir -p "$1/dir1/dir2/stale-dir/nested" "$1/dir1/keep"
+	touch "$1/dir1/stale" "$1/dir1/dir2/stale-dir/nested/file"
+	touch "$1/dir1/keep/data" "$1/dir1/file.log" "$1/outside"
+}
+
+function test_delete {
+	local dst="$PWD/delete-tree"
+
+	add_stale_files "$dst"
+
+	turboinstall --delete \
+		--protect '^/dir1/keep/' \
+		--ignore '\.log$' \
+		-- "$dst" "$src"
+
+	mapfile -t dst_paths < <(dir_tree_contents "$dst")
+
+	assert_eq "${dst_paths[*]}" \
+		"/ /dir1 /dir1/dir2 /dir1/dir2/file2 /dir1/file1 /dir1/file.log /dir1/keep /dir1/keep/data /file0 /outside"
+}
+
+function test_delete_dry_run {
+	local dst="$PWD/delete-tree-dry-run"
+
+	add_stale_files "$dst"
+	turboinstall -- "$dst" "$src"
+
+	mapfile -t before < <(dir_tree_contents "$dst")
+
+	turboinstall --delete --dry-run --porcelain -- "$dst" "$src" > "$dst.out"
+
+	mapfile -t after < <(dir_tree_contents "$dst")
+
+	assert_eq "${after[*]}" "${before[*]}"
+	assert grep -q "^$dst/dir1/dir2/stale-dir$" "$dst.out"
+}
+
+function test_delete_rollback {
+	local dst="$PWD/delete-tree-rollback"
+	local failing="$PWD/delete-tree-failing"
+
+	mkdir -p "$failing/.turboinstall/post-install"
+	printf '#!/bin/sh\nexit 1\n' > "$failing/.turboinstall/post-install/fail.sh"
+	chmod +x "$failing/.turboinstall/post-install/fail.sh"
+
+	add_stale_files "$dst"
+	turboinstall -- "$dst" "$src"
+
+	mapfile -t before < <(dir_tree_contents "$dst")
+
+	! turboinstall --transaction --delete -- "$dst" "$src" "$failing"
+
+	mapfile -t after < <(dir_tree_contents "$dst")
+
+	assert_eq "${after[*]}" "${before[*]}"
+}
+
+function test_delete_overlay_ignores {
+	local dst="$PWD/delete-tree-overlay-ignores"
+	local src="$PWD/delete-tree-overlay-ignores-src"
+
+	mkdir -p "$src/.turboinstall" "$src/{DIR}/app/cache" "$dst/usr/local/app/cache"
+	touch "$src/{DIR}/app/main.py" "$src/{DIR}/app/main.pyc"
+	touch "$dst/usr/local/app/main.pyc" "$dst/usr/local/app/stale" \
+		"$dst/usr/local/app/cache/data" "$dst/usr/local/app/data.db"
+
+	echo '\.pyc$' > "$src/.turboinstall/ignore"
+	printf 'cache/\n*.db\n' > "$src/{DIR}/app/.turboinstallignore"
+
+	turboinstall --delete -p "$TEST_DIR/profile-tree/.turboinstall/profile.json" \
+		-- "$dst" "$src"
+
+	mapfile -t dst_paths < <(dir_tree_contents "$dst")
+
+	assert_eq "${dst_paths[*]}" \
+		"/ /usr /usr/local /usr/local/app /usr/local/app/cache /usr/local/app/cache/data /usr/local/app/data.db /usr/local/app/main.py /usr/local/app/main.pyc"
+}
+
+function test_delete_relative {
+	local dst="$PWD/delete-tree-relative"
+
+	add_stale_files "$dst"
+
+	# turboinstall runs from the repository
+	turboinstall --delete -- "${dst#"$REPO_DIR/"}" "$src"
+
+	assert [ ! -e "$dst/dir1/stale" ]
+	assert [ -e "$dst/outside" ]
+}
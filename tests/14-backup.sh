@@ -0,0 +1,103 @@
+#!/bin/bash
+
+src="$TEST_DIR/simple-tree"
+
+# $1 - path to the destination
+function make_edited_dst {
+	mkdir -p "$1/dir1"
+	echo "edited" > "$1/file0"
+	echo "edited" > "$1/dir1/file1"
+}
+
+function test_backup_simple {
+	local dst="$PWD/backup-tree"
+
+	make_edited_dst "$dst"
+
+	turboinstall --backup -- "$dst" "$src"
+
+	assert_eq "$(cat "$dst/file0~")" "edited"
+	assert_eq "$(cat "$dst/dir1/file1~")" "edited"
+	assert cmp -s "$dst/file0" "$src/file0"
+	assert [ ! -e "$dst/dir1/dir2/file2~" ]
+}
+
+function test_backup_suffix {
+	local dst="$PWD/backup-tree-suffix"
+
+	make_edited_dst "$dst"
+
+	turboinstall --suffix .orig -- "$dst" "$src"
+
+	assert_eq "$(cat "$dst/file0.orig")" "edited"
+}
+
+function test_backup_numbered {
+	local dst="$PWD/backup-tree-numbered"
+
+	make_edited_dst "$dst"
+
+	turboinstall --backup=numbered -- "$dst" "$src"
+	echo "edited again" > "$dst/file0"
+	turboinstall --backup=numbered -- "$dst" "$src"
+
+	assert_eq "$(cat "$dst/file0.~1~")" "edited"
+	assert_eq "$(cat "$dst/file0.~2~")" "edited again"
+}
+
+function test_backup_numbered_existing {
+	local dst="$PWD/backup-tree-numbered-existing"
+
+	make_edited_dst "$dst"
+	touch "$dst/file0.~1~" "$dst/file0.~12~" "$dst/file0.~x~" "$dst/other.~40~"
+
+	turboinstall --backup=numbered -- "$dst" "$src"
+
+	assert_eq "$(cat "$dst/file0.~13~")" "edited"
+	assert [ ! -e "$dst/file0.~41~" ]
+}
+
+function test_backup_dir {
+	local dst="$PWD/backup-tree-dir"
+	local backup_dir="$PWD/backup-tree-dir.bak"
+
+	make_edited_dst "$dst"
+
+	turboinstall --backup-dir "$backup_dir" -- "$dst" "$src"
+
+	assert_eq "$(cat "$backup_dir/file0")" "edited"
+	assert_eq "$(cat "$backup_dir/dir1/file1")" "edited"
+	assert [ ! -e "$dst/file0~" ]
+}
+
+function test_backup_rollback {
+	local dst="$PWD/backup-tree-rollback"
+	local backup_dir="$PWD/backup-tree-rollback.bak"
+	local failing="$PWD/backup-tree-failing"
+
+	mkdir -p "$failing/.turboinstall/post-install"
+	printf '#!/bin/sh\nexit 1\n' > "$failing/.turboinstall/post-install/fail.sh"
+	chmod +x "$failing/.turboinstall/post-install/fail.sh"
+
+	make_edited_dst "$dst"
+
+	! turboinstall --transaction --backup-dir "$backup_dir" -- "$dst" "$src" "$failing"
+
+	assert_eq "$(cat "$dst/file0")" "edited"
+	assert [ ! -e "$backup_dir" ]
+}
+
+function test_backup_symlink {
+	local dst="$PWD/backup-tree-symlink"
+	local outside="$PWD/backup-tree-symlink-outside"
+
+	mkdir "$dst"
+	echo "edited" > "$outside"
+	ln -s "$outside" "$dst/file0"
+
+	turboinstall --backup -- "$dst" "$src"
+
+	assert [ ! -L "$dst/file0~" ]
+	assert_eq "$(cat "$dst/file0~")" "edited"
+	assert cmp -s "$outside" "$src/file0"
+}
@@ -0,0 +1,74 @@
+#!/bin/bash
+
+src="$TEST_DIR/simple-tree"
+
+# $1 - path to dir
+function dir_tree_contents {
+	(cd -- "$1" && find . -printf '/%P\n' | sort -df)
+}
+
+function test_replace_by_rename {
+	local dst="$PWD/atomic-tree"
+
+	mkdir -p "$dst"
+	echo "old" > "$dst/file0"
+	ln "$dst/file0" "$PWD/atomic-tree.old"
+
+	turboinstall --fsync -- "$dst" "$src"
+
+	# the old inode is left untouched instead of being truncated
+	assert_eq "$(cat "$PWD/atomic-tree.old")" "old"
+	assert cmp -s "$dst/file0" "$src/file0"
+
+	mapfile -t dst_paths < <(dir_tree_contents "$dst")
+
+	assert_eq "${dst_paths[*]}" \
+		"/ /dir1 /dir1/dir2 /dir1/dir2/file2 /dir1/file1 /file0"
+}
+
+function test_install_through_symlink {
+	local dst="$PWD/atomic-tree-symlink"
+
+	mkdir -p "$dst"
+	echo "old" > "$PWD/atomic-tree-symlink.target"
+	ln -s "$PWD/atomic-tree-symlink.target" "$dst/file0"
+
+	turboinstall -- "$dst" "$src"
+
+	assert [ -L "$dst/file0" ]
+	assert cmp -s "$PWD/atomic-tree-symlink.target" "$src/file0"
+}
+
+function test_keep_owner {
+	local dst="$PWD/atomic-tree-owner"
+
+	# only root can give files away
+	if [ "$(id -u)" != 0 ]; then
+		return 0
+	fi
+
+	mkdir -p "$dst"
+	echo "old" > "$dst/file0"
+	chown 65534:65534 "$dst/file0"
+
+	turboinstall -- "$dst" "$src"
+
+	assert_eq "$(stat -c %u:%g "$dst/file0")" "65534:65534"
+	assert cmp -s "$dst/file0" "$src/file0"
+}
+
+function test_fsync_read_only {
+	local dst="$PWD/atomic-tree-read-only"
+	local dst_link="$PWD/atomic-tree-read-only-link"
+	local ro_src="$PWD/atomic-tree-read-only-src"
+
+	mkdir -p "$ro_src" "$dst" "$dst_link"
+	echo "data" > "$ro_src/file0"
+	chmod 0444 "$ro_src/file0"
+
+	turboinstall --fsync -- "$dst" "$ro_src"
+	turboinstall --fsync --link -- "$dst_link" "$ro_src"
+
+	assert cmp -s "$dst/file0" "$ro_src/file0"
+	assert [ "$dst_link/file0" -ef "$ro_src/file0" ]
+}
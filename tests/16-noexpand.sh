@@ -0,0 +1,36 @@
+#!/bin/bash
+
+# $1 - path to dir
+function dir_tree_contents {
+	(cd -- "$1" && find . -printf '/%P\n' | sort -df)
+}
+
+profile="$TEST_DIR/profile-tree/.turboinstall/profile.json"
+
+function test_escaped_braces {
+	local dst="$PWD/noexpand-tree-escape"
+	local src="$PWD/noexpand-tree-escape-src"
+
+	mkdir -p "$src/{DIR}" "$dst"
+	touch "$src/{DIR}/foo@{{}}.service"
+
+	turboinstall -p "$profile" -- "$dst" "$src"
+
+	assert [ -f "$dst/usr/local/foo@{}.service" ]
+}
+
+function test_noexpand_subtree {
+	local dst="$PWD/noexpand-tree"
+	local src="$PWD/noexpand-tree-src"
+
+	mkdir -p "$src/.turboinstall" "$src/{DIR}/share/{templates}" "$dst"
+	touch "$src/{DIR}/share/{templates}/{VARIABLE_1}"
+	echo '^/\{DIR\}/share/\{templates\}$' > "$src/.turboinstall/noexpand"
+
+	turboinstall -p "$profile" -- "$dst" "$src"
+
+	mapfile -t dst_paths < <(dir_tree_contents "$dst")
+
+	assert_eq "${dst_paths[*]}" \
+		"/ /usr /usr/local /usr/local/share /usr/local/share/{templates} /usr/local/share/{templates}/{VARIABLE_1}"
+}
@@ -0,0 +1,95 @@
+#!/bin/bash
+
+profile="$TEST_DIR/profile-tree/.turboinstall/profile.json"
+
+# $1 - path to the new source tree
+function make_template_tree {
+	mkdir -p "$1/.turboinstall" "$1/etc"
+
+	printf 'prefix={DIR}\nliteral={{DIR}}\n' > "$1/etc/app.conf.tmpl"
+	printf 'value={VARIABLE_1}\n' > "$1/etc/listed.conf"
+	printf 'value={VARIABLE_1}\n' > "$1/etc/plain.conf"
+	printf 'value={VARIABLE_1}\n' > "$1/etc/option.conf"
+
+	printf '^/etc/listed\\.conf$\n\\.tmpl$\n' > "$1/.turboinstall/templates"
+}
+
+function test_templates {
+	local dst="$PWD/template-tree"
+	local src="$PWD/template-tree-src"
+
+	make_template_tree "$src"
+	mkdir "$dst"
+
+	turboinstall -p "$profile" --template 'option.conf' -- "$dst" "$src"
+
+	assert [ ! -e "$dst/etc/app.conf.tmpl" ]
+	assert_eq "$(cat "$dst/etc/app.conf")" "$(printf 'prefix=/usr/local\nliteral={DIR}')"
+	assert_eq "$(cat "$dst/etc/listed.conf")" "value=VALUE_1"
+	assert_eq "$(cat "$dst/etc/option.conf")" "value=VALUE_1"
+	assert_eq "$(cat "$dst/etc/plain.conf")" "value={VARIABLE_1}"
+}
+
+function test_template_checksum {
+	local dst="$PWD/template-tree-checksum"
+	local src="$PWD/template-tree-checksum-src"
+
+	make_template_tree "$src"
+	mkdir "$dst"
+
+	turboinstall -p "$profile" -- "$dst" "$src"
+	turboinstall -p "$profile" verify -- "$dst" "$src"
+
+	touch -d '2000-01-01' "$dst/etc/app.conf"
+	turboinstall -p "$profile" --checksum -- "$dst" "$src"
+
+	assert_eq "$(stat -c %Y "$dst/etc/app.conf")" "$(date -d '2000-01-01' +%s)"
+}
+
+function test_template_missing_variable {
+	local dst="$PWD/template-tree-missing"
+	local src="$PWD/template-tree-missing-src"
+
+	mkdir -p "$src" "$dst"
+	echo '{MISSING}' > "$src/file.tmpl"
+
+	! turboinstall --template '*.tmpl' -- "$dst" "$src"
+}
+
+function test_template_opt_in {
+	local dst="$PWD/template-tree-opt-in"
+	local src="$PWD/template-tree-opt-in-src"
+
+	mkdir -p "$src" "$dst"
+	echo 'Hello {{ .Name }}' > "$src/page.html.tmpl"
+
+	turboinstall -- "$dst" "$src"
+
+	assert [ ! -e "$dst/page.html" ]
+	assert_eq "$(cat "$dst/page.html.tmpl")" 'Hello {{ .Name }}'
+}
+
+function test_template_blocks {
+	local dst="$PWD/template-tree-blocks"
+	local src="$PWD/template-tree-blocks-src"
+
+	mkdir -p "$src" "$dst"
+	printf 'server {\n\troot {DIR};\n\tset $x "{ }";\n}\n' > "$src/site.conf"
+
+	turboinstall -p "$profile" --template 'site.conf' -- "$dst" "$src"
+
+	assert_eq "$(cat "$dst/site.conf")" "$(printf 'server {\n\troot /usr/local;\n\tset $x "{ }";\n}')"
+}
+
+function test_template_dry_run {
+	local dst="$PWD/template-tree-dry-run"
+	local src="$PWD/template-tree-dry-run-src"
+
+	mkdir -p "$src" "$dst"
+	echo '{MISSING}' > "$src/file.tmpl"
+
+	# templates are only rendered when they are installed
+	turboinstall --dry-run --template '*.tmpl' -- "$dst" "$src"
+
+	assert [ ! -e "$dst/file" ]
+}
@@ -0,0 +1,40 @@
+#!/bin/bash
+
+# $1 - path to dir
+function dir_tree_contents {
+	(cd -- "$1" && find . -printf '/%P\n' | sort -df)
+}
+
+src="$TEST_DIR/profile-tree"
+profile="$src/.turboinstall/profile.json"
+
+function test_define {
+	local dst="$PWD/define-tree"
+
+	mkdir "$dst"
+
+	turboinstall -D DIR=/opt -D VARIABLE_1=one -- "$dst" "$src"
+
+	mapfile -t dst_paths < <(dir_tree_contents "$dst")
+
+	assert_eq "${dst_paths[*]}" \
+		"/ /one /opt /opt/file_one"
+}
+
+function test_define_overrides_profile {
+	local dst="$PWD/define-tree-override"
+
+	mkdir "$dst"
+
+	TURBO_DIR=/srv TURBO_VARIABLE_1=env \
+		turboinstall -p "$profile" --env-prefix TURBO_ -D VARIABLE_1=cli -- "$dst" "$src"
+
+	mapfile -t dst_paths < <(dir_tree_contents "$dst")
+
+	assert_eq "${dst_paths[*]}" \
+		"/ /cli /srv /srv/file_cli"
+}
+
+function test_define_invalid {
+	! turboinstall -D DIR -- "$PWD" "$src"
+}
@@ -0,0 +1,94 @@
+#!/bin/bash
+
+# $1 - path to dir
+function dir_tree_contents {
+	(cd -- "$1" && find . -printf '/%P\n' | sort -df)
+}
+
+src="$TEST_DIR/profile-tree"
+
+function test_multiple_profiles {
+	local dst="$PWD/layered-tree"
+	local tree="$PWD/layered-tree-src"
+	local override="$PWD/layered-tree-override.env"
+
+	mkdir "$dst"
+	echo 'DIR=/opt' > "$override"
+
+	# only the given profiles can provide the variables
+	cp -r "$src" "$tree"
+	rm "$tree"/.turboinstall/profile.*
+
+	turboinstall \
+		-p "$src/.turboinstall/profile.json" \
+		-p "$override" \
+		-- "$dst" "$tree"
+
+	mapfile -t dst_paths < <(dir_tree_contents "$dst")
+
+	assert_eq "${dst_paths[*]}" \
+		"/ /opt /opt/file_VALUE_1 /VALUE_1"
+}
+
+function test_extends {
+	local dst="$PWD/layered-tree-extends"
+	local profiles="$PWD/layered-tree-extends-profiles"
+
+	mkdir -p "$dst" "$profiles/base"
+	printf 'DIR = "/usr"\nVARIABLE_1 = "base"\n' > "$profiles/base/common.toml"
+	printf '{ "extends": "base/common.toml", "VARIABLE_1": "target" }' > "$profiles/target.json"
+
+	turboinstall -p "$profiles/target.json" -- "$dst" "$src"
+
+	mapfile -t dst_paths < <(dir_tree_contents "$dst")
+
+	assert_eq "${dst_paths[*]}" \
+		"/ /target /usr /usr/file_target"
+}
+
+function test_include_cycle {
+	local profiles="$PWD/layered-tree-cycle"
+
+	mkdir -p "$profiles"
+	echo 'include=b.env' > "$profiles/a.env"
+	echo 'include=a.env' > "$profiles/b.env"
+
+	mkdir "$profiles/dst"
+
+	! turboinstall -p "$profiles/a.env" -- "$profiles/dst" "$src"
+}
+
+function test_references {
+	local dst="$PWD/layered-tree-references"
+	local src="$PWD/layered-tree-references-src"
+
+	mkdir -p "$dst" "$src/.turboinstall" "$src/{BIN}"
+	touch "$src/{BIN}/tool"
+	printf 'PREFIX=/usr\nBIN={PREFIX}/bin\n' > "$src/.turboinstall/profile.env"
+
+	turboinstall -D PREFIX=/opt -- "$dst" "$src"
+
+	assert [ -f "$dst/opt/bin/tool" ]
+}
+
+function test_reference_cycle {
+	local dst="$PWD/layered-tree-reference-cycle"
+	local src="$PWD/layered-tree-reference-cycle-src"
+
+	mkdir -p "$dst" "$src/.turboinstall" "$src/{A}"
+	printf 'A={B}\nB={A}\n' > "$src/.turboinstall/profile.env"
+
+	! turboinstall -- "$dst" "$src"
+}
+
+function test_references_lazy {
+	local dst="$PWD/layered-tree-references-lazy"
+	local src="$PWD/layered-tree-references-lazy-src"
+
+	mkdir -p "$dst" "$src/.turboinstall" "$src/{DIR}"
+	printf '{ "DIR": "/usr", "LOG_FORMAT": "{time} {msg}" }' > "$src/.turboinstall/profile.json"
+
+	TURBO_X='a{b}' turboinstall --env-prefix TURBO_ -D 'Y={Z}' -- "$dst" "$src"
+
+	assert [ -d "$dst/usr" ]
+}
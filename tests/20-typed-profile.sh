@@ -0,0 +1,55 @@
+#!/bin/bash
+
+# $1 - path to dir
+function dir_tree_contents {
+	(cd -- "$1" && find . -printf '/%P\n' | sort -df)
+}
+
+function test_typed_profile {
+	local dst="$PWD/typed-tree"
+	local src="$PWD/typed-tree-src"
+
+	mkdir -p "$dst" "$src/{paths.bin}" "$src/.turboinstall/post-install"
+	touch "$src/{paths.bin}/tool-{VERSION}-{DEBUG}"
+
+	cat > "$src/.turboinstall/profile.yaml" <<-EOT
+	VERSION: 2
+	DEBUG: false
+	LIBS:
+	  - liba
+	  - libb
+	paths:
+	  bin: /usr/bin
+	EOT
+
+	cat > "$src/.turboinstall/post-install/env.sh" <<-'EOT'
+	#!/bin/sh
+	printf '%s\n' "$paths_bin" "$LIBS" > "$2/hook.out"
+	EOT
+	chmod +x "$src/.turboinstall/post-install/env.sh"
+
+	turboinstall -- "$dst" "$src"
+
+	mapfile -t dst_paths < <(dir_tree_contents "$dst")
+
+	assert_eq "${dst_paths[*]}" \
+		"/ /hook.out /usr /usr/bin /usr/bin/tool-2-false"
+	assert_eq "$(cat "$dst/hook.out")" "$(printf '/usr/bin\nliba\nlibb')"
+}
+
+function test_include_list {
+	local dst="$PWD/typed-tree-include"
+	local profiles="$PWD/typed-tree-include-profiles"
+
+	mkdir -p "$dst" "$profiles"
+	echo 'DIR=/a' > "$profiles/a.env"
+	printf 'DIR=/b\nVARIABLE_1=b\n' > "$profiles/b.env"
+	echo '{ "include": ["a.env", "b.env"], "DIR": "/c" }' > "$profiles/c.json"
+
+	turboinstall -p "$profiles/c.json" -- "$dst" "$TEST_DIR/profile-tree"
+
+	mapfile -t dst_paths < <(dir_tree_contents "$dst")
+
+	assert_eq "${dst_paths[*]}" \
+		"/ /b /c /c/file_b"
+}
@@ -0,0 +1,69 @@
+#!/bin/bash
+
+# $1 - path to dir
+function dir_tree_contents {
+	(cd -- "$1" && find . -printf '/%P\n' | sort -df)
+}
+
+# $1 - path to src
+function make_glob_tree {
+	mkdir -p "$1/.turboinstall" "$1/build" "$1/dir1/build" "$1/dir1/cache" "$1/dir2"
+	touch "$1/file0.o" "$1/file0.c" "$1/build/file1" "$1/dir1/build/file2" \
+		"$1/dir1/cache/file3" "$1/dir2/cache" "$1/dir2/file4.o"
+}
+
+function test_glob_file_name {
+	local dst="$PWD/glob-tree-name"
+	local src="$PWD/glob-tree-name-src"
+
+	make_glob_tree "$src"
+	mkdir -p "$dst"
+
+	cat > "$src/.turboinstall/ignore.glob" <<-'END'
+	*.o
+	/build
+	cache/
+	END
+
+	turboinstall -- "$dst" "$src"
+
+	mapfile -t dst_paths < <(dir_tree_contents "$dst")
+
+	assert_eq "${dst_paths[*]}" \
+		"/ /dir1 /dir1/build /dir1/build/file2 /dir2 /dir2/cache /file0.c"
+}
+
+function test_glob_header {
+	local dst="$PWD/glob-tree-header"
+	local src="$PWD/glob-tree-header-src"
+
+	make_glob_tree "$src"
+	mkdir -p "$dst"
+
+	cat > "$src/.turboinstall/ignore" <<-'END'
+	^/file0\.c$
+
+	syntax: glob
+	**/build/**
+	dir?/*.o
+	END
+
+	turboinstall -- "$dst" "$src"
+
+	mapfile -t dst_paths < <(dir_tree_contents "$dst")
+
+	assert_eq "${dst_paths[*]}" \
+		"/ /build /dir1 /dir1/build /dir1/cache /dir1/cache/file3 /dir2 /dir2/cache /file0.o"
+}
+
+function test_unknown_syntax {
+	local dst="$PWD/glob-tree-unknown"
+	local src="$PWD/glob-tree-unknown-src"
+
+	make_glob_tree "$src"
+	mkdir -p "$dst"
+
+	echo 'syntax: shell' > "$src/.turboinstall/ignore"
+
+	! turboinstall -- "$dst" "$src"
+}
@@ -0,0 +1,45 @@
+#!/bin/bash
+
+# $1 - path to dir
+function dir_tree_contents {
+	(cd -- "$1" && find . -printf '/%P\n' | sort -df)
+}
+
+function test_negated_glob {
+	local dst="$PWD/negated-tree"
+	local src="$PWD/negated-tree-src"
+
+	mkdir -p "$src/.turboinstall" "$src/docs/html" "$src/docs/man/man1" "$dst"
+	touch "$src/docs/index.html" "$src/docs/html/page.html" "$src/docs/man/man1/foo.1"
+
+	cat > "$src/.turboinstall/ignore.glob" <<-'END'
+	/docs/**
+	!/docs/man
+	END
+
+	turboinstall -- "$dst" "$src"
+
+	mapfile -t dst_paths < <(dir_tree_contents "$dst")
+
+	assert_eq "${dst_paths[*]}" \
+		"/ /docs /docs/man /docs/man/man1 /docs/man/man1/foo.1"
+}
+
+function test_negated_regex {
+	local dst="$PWD/negated-tree-regex"
+	local src="$PWD/negated-tree-regex-src"
+
+	mkdir -p "$src/.turboinstall" "$dst"
+	touch "$src/file0.bak" "$src/file1.bak" "$src/file2"
+
+	cat > "$src/.turboinstall/ignore" <<-'END'
+	\.bak$
+	!^/file1\.bak$
+	END
+
+	turboinstall -- "$dst" "$src"
+
+	mapfile -t dst_paths < <(dir_tree_contents "$dst")
+
+	assert_eq "${dst_paths[*]}" "/ /file1.bak /file2"
+}
@@ -0,0 +1,71 @@
+#!/bin/bash
+
+# $1 - path to dir
+function dir_tree_contents {
+	(cd -- "$1" && find . -printf '/%P\n' | sort -df)
+}
+
+function test_nested_ignore {
+	local dst="$PWD/nested-tree"
+	local src="$PWD/nested-tree-src"
+
+	mkdir -p "$src/.turboinstall" "$src/dir1/dir2" "$src/dir3" "$dst"
+	touch "$src/file0" "$src/file1.o" "$src/dir1/file0" "$src/dir1/file1.o" \
+		"$src/dir1/keep.o" "$src/dir1/dir2/file0" "$src/dir3/file0"
+
+	echo '\.o$' > "$src/.turboinstall/ignore"
+	printf '/file0\n!keep.o\n' > "$src/dir1/.turboinstallignore"
+
+	turboinstall -- "$dst" "$src"
+
+	mapfile -t dst_paths < <(dir_tree_contents "$dst")
+
+	assert_eq "${dst_paths[*]}" \
+		"/ /dir1 /dir1/dir2 /dir1/dir2/file0 /dir1/keep.o /dir3 /dir3/file0 /file0"
+}
+
+function test_nested_ignore_root {
+	local dst="$PWD/nested-tree-root"
+	local src="$PWD/nested-tree-root-src"
+
+	mkdir -p "$src/dir1" "$dst"
+	touch "$src/file0" "$src/dir1/file0" "$src/dir1/file1"
+
+	printf 'file0\n' > "$src/.turboinstallignore"
+	printf 'syntax: regex\n^/file1$\n' > "$src/dir1/.turboinstallignore"
+
+	turboinstall -- "$dst" "$src"
+
+	mapfile -t dst_paths < <(dir_tree_contents "$dst")
+
+	assert_eq "${dst_paths[*]}" "/ /dir1"
+}
+
+function test_nested_ignore_scope {
+	local dst="$PWD/nested-tree-scope"
+	local src="$PWD/nested-tree-scope-src"
+
+	mkdir -p "$src/dir1/dir2" "$src/dir3" "$dst"
+	touch "$src/a.tmp" "$src/dir1/a.tmp" "$src/dir1/dir2/a.tmp" "$src/dir3/a.tmp"
+
+	echo '*.tmp' > "$src/dir1/.turboinstallignore"
+
+	turboinstall -- "$dst" "$src"
+
+	mapfile -t dst_paths < <(dir_tree_contents "$dst")
+
+	assert_eq "${dst_paths[*]}" \
+		"/ /a.tmp /dir1 /dir1/dir2 /dir3 /dir3/a.tmp"
+}
+
+function test_nested_ignore_invalid {
+	local dst="$PWD/nested-tree-invalid"
+	local src="$PWD/nested-tree-invalid-src"
+
+	mkdir -p "$src/dir1" "$dst"
+	touch "$src/dir1/file0"
+
+	printf 'syntax: shell\n' > "$src/dir1/.turboinstallignore"
+
+	! turboinstall -- "$dst" "$src"
+}
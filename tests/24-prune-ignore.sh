@@ -0,0 +1,38 @@
+#!/bin/bash
+
+# $1 - path to dir
+function dir_tree_contents {
+	(cd -- "$1" && find . -printf '/%P\n' | sort -df)
+}
+
+function test_prune_ignored_dir {
+	local dst="$PWD/prune-tree"
+	local src="$PWD/prune-tree-src"
+
+	mkdir -p "$src/.turboinstall" "$src/vendor/dir1" "$src/dir1/vendor" "$dst"
+	touch "$src/file0" "$src/vendor/file1" "$src/vendor/dir1/file2" "$src/dir1/vendor/file3"
+
+	echo '^/vendor$' > "$src/.turboinstall/ignore"
+
+	turboinstall -- "$dst" "$src"
+
+	mapfile -t dst_paths < <(dir_tree_contents "$dst")
+
+	assert_eq "${dst_paths[*]}" \
+		"/ /dir1 /dir1/vendor /dir1/vendor/file3 /file0"
+}
+
+function test_prune_no_reinclude {
+	local dst="$PWD/prune-tree-reinclude"
+	local src="$PWD/prune-tree-reinclude-src"
+
+	mkdir -p "$src/docs/man" "$dst"
+	touch "$src/docs/index.html" "$src/docs/man/foo.1"
+	printf '/docs\n!/docs/man\n' > "$src/.turboinstallignore"
+
+	turboinstall -- "$dst" "$src"
+
+	mapfile -t dst_paths < <(dir_tree_contents "$dst")
+
+	assert_eq "${dst_paths[*]}" "/"
+}
@@ -0,0 +1,89 @@
+#!/bin/bash
+
+# $1 - path to dir
+function dir_tree_contents {
+	(cd -- "$1" && find . -printf '/%P\n' | sort -df)
+}
+
+# $1 - path to src
+function make_conditional_tree {
+	mkdir -p "$1/.turboinstall" "$1/lib/aarch64" "$1/lib/x86_64" "$1/feature_x"
+	touch "$1/lib/aarch64/file0" "$1/lib/x86_64/file0" "$1/feature_x/file1"
+
+	cat > "$1/.turboinstall/ignore.glob" <<-'END'
+	/lib/*
+	!/lib/{ARCH}
+
+	[unless FEATURE_X]
+	/feature_x
+	[end]
+	END
+}
+
+function test_conditional_ignore {
+	local dst="$PWD/conditional-tree"
+	local src="$PWD/conditional-tree-src"
+
+	make_conditional_tree "$src"
+	mkdir -p "$dst"
+
+	turboinstall -D ARCH=aarch64 -- "$dst" "$src"
+
+	mapfile -t dst_paths < <(dir_tree_contents "$dst")
+
+	assert_eq "${dst_paths[*]}" "/ /lib /lib/aarch64 /lib/aarch64/file0"
+}
+
+function test_conditional_ignore_feature {
+	local dst="$PWD/conditional-tree-feature"
+	local src="$PWD/conditional-tree-feature-src"
+
+	make_conditional_tree "$src"
+	mkdir -p "$dst"
+
+	turboinstall -D ARCH=x86_64 -D FEATURE_X=1 -- "$dst" "$src"
+
+	mapfile -t dst_paths < <(dir_tree_contents "$dst")
+
+	assert_eq "${dst_paths[*]}" \
+		"/ /feature_x /feature_x/file1 /lib /lib/x86_64 /lib/x86_64/file0"
+}
+
+function test_conditional_ignore_missing_var {
+	local dst="$PWD/conditional-tree-missing"
+	local src="$PWD/conditional-tree-missing-src"
+
+	make_conditional_tree "$src"
+	mkdir -p "$dst"
+
+	! turboinstall -- "$dst" "$src"
+}
+
+function test_conditional_templates {
+	local dst="$PWD/conditional-tree-templates"
+	local src="$PWD/conditional-tree-templates-src"
+
+	mkdir -p "$src/.turboinstall" "$dst"
+	echo 'value={ARCH}' > "$src/arch.txt"
+	echo 'value={MISSING}' > "$src/info.txt"
+	touch "$src/{ARCH}.conf"
+
+	cat > "$src/.turboinstall/templates" <<-'END'
+	[if ARCH=arm]
+	^/info\.txt$
+	[end]
+	^/arch\.txt$
+	END
+
+	cat > "$src/.turboinstall/noexpand" <<-'END'
+	[unless ARCH]
+	.*
+	[end]
+	END
+
+	turboinstall -D ARCH=aarch64 -- "$dst" "$src"
+
+	assert_eq "$(cat "$dst/arch.txt")" "value=aarch64"
+	assert_eq "$(cat "$dst/info.txt")" "value={MISSING}"
+	assert [ -f "$dst/aarch64.conf" ]
+}